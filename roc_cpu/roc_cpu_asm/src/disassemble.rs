@@ -0,0 +1,268 @@
+//! Turning instructions back into Roc assembly.
+//!
+//! The text this produces assembles back into exactly the
+//! same instructions. Jump and call targets become labels,
+//! named from a symbol table when there is one, and `@L`
+//! followed by the instruction index when there isn't.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use roc_cpu_traits::{ProgramDecodable, ProgramDecodeError};
+use roc_cpu_types::*;
+
+use crate::DATA_START;
+
+/// How many bytes go on each `.byte` line.
+const BYTES_PER_LINE: usize = 16;
+
+
+/// Disassembles an instruction list, where jump and call
+/// targets are instruction indexes, along with its data.
+pub fn disassemble(program: &RocCPUProgram) -> String {
+    disassemble_with_labels(program, &HashMap::new())
+}
+
+/// Like `disassemble`, but `labels` names some instruction
+/// indexes. Names that aren't valid labels are ignored.
+pub fn disassemble_with_labels(program: &RocCPUProgram, labels: &HashMap<usize, String>) -> String {
+    let len = program.len();
+    let lines: Vec<_> = program.iter()
+        .map(|instruction| {
+            let target = instruction.jump_target()
+                .map(|target| target as usize)
+                .filter(|target| *target <= len);
+            (*instruction, target)
+        })
+        .collect();
+
+    let segments: Vec<_> = program.data.iter()
+        .map(|segment| (segment.address, segment.bytes.as_slice()))
+        .collect();
+
+    write_program(&lines, labels, &segments)
+}
+
+/// Decodes and disassembles `image`, which was loaded at
+/// `load_address`, so its jump and call targets are byte
+/// addresses. `symbols` names some of those addresses.
+///
+/// Anything from `DATA_START` on is data, as
+/// `assemble_to_image` lays it out, and the zeroes
+/// padding the code out to it are left out.
+pub fn disassemble_image(
+    image: &[u8],
+    load_address: u16,
+    symbols: &HashMap<u16, String>
+) -> Result<String, ProgramDecodeError> {
+    let data_offset = (DATA_START as usize).checked_sub(load_address as usize)
+        .filter(|offset| *offset < image.len());
+    let (code, data) = match data_offset {
+        Some(offset) => image.split_at(offset),
+        None => (image, &[][..]),
+    };
+
+    let mut instructions = vec![];
+    let mut indexes = HashMap::new();
+    let mut offset = 0;
+
+    while offset < code.len() {
+        // No instruction starts with a zero, so these
+        // can only be padding.
+        if data_offset.is_some() && code[offset..].iter().all(|byte| *byte == 0) {
+            break;
+        }
+
+        let (instruction, used) = RocCPUInstruction::decode(&code[offset..])?;
+        indexes.insert(load_address as usize + offset, instructions.len());
+        instructions.push(instruction);
+        offset += used;
+    }
+    indexes.insert(load_address as usize + offset, instructions.len());
+
+    // Only targets that land on an instruction can become
+    // labels, anything else is left as `hi, lo`.
+    let lines: Vec<_> = instructions.iter()
+        .map(|instruction| {
+            let target = instruction.jump_target()
+                .and_then(|target| indexes.get(&(target as usize)).copied());
+            (*instruction, target)
+        })
+        .collect();
+
+    let labels = symbols.iter()
+        .filter_map(|(address, name)| {
+            indexes.get(&(*address as usize)).map(|index| (*index, name.clone()))
+        })
+        .collect();
+
+    let segments = if data.is_empty() { vec![] } else { vec![(DATA_START, data)] };
+    Ok(write_program(&lines, &labels, &segments))
+}
+
+
+/// Writes `lines`, each an instruction and the index of
+/// the instruction it jumps to, if any.
+pub(crate) fn write_program(
+    lines: &[(RocCPUInstruction, Option<usize>)],
+    labels: &HashMap<usize, String>,
+    segments: &[(u16, &[u8])]
+) -> String {
+    let names = label_names(lines, labels);
+
+    // A label just past the last instruction would mark
+    // whatever comes after it, so the data goes first
+    // when there's one of those.
+    let end_label = names.get(&lines.len());
+    let mut output = String::new();
+    if end_label.is_some() {
+        write_data(&mut output, segments);
+    }
+
+    for (index, (instruction, target)) in lines.iter().enumerate() {
+        if let Some(name) = names.get(&index) {
+            writeln!(output, "@{}", name).unwrap();
+        }
+
+        match target {
+            Some(target) => {
+                writeln!(output, "    {} @{};", instruction.mnemonic(), names[target]).unwrap();
+            },
+            None => {
+                writeln!(output, "    {};", instruction).unwrap();
+            },
+        }
+    }
+
+    match end_label {
+        Some(name) => writeln!(output, "@{}", name).unwrap(),
+        None => write_data(&mut output, segments),
+    }
+    output
+}
+
+/// A name for every instruction index that needs a label.
+fn label_names(
+    lines: &[(RocCPUInstruction, Option<usize>)],
+    labels: &HashMap<usize, String>
+) -> BTreeMap<usize, String> {
+    let mut names = BTreeMap::new();
+    let mut used = std::collections::HashSet::new();
+
+    let mut sorted: Vec<_> = labels.iter().collect();
+    sorted.sort();
+    for (index, name) in sorted {
+        if *index <= lines.len() && is_label_name(name) && used.insert(name.clone()) {
+            names.entry(*index).or_insert_with(|| name.clone());
+        }
+    }
+
+    for (_, target) in lines {
+        if let Some(target) = target {
+            names.entry(*target).or_insert_with(|| {
+                // Skip past any real label that happens to
+                // look like one of ours.
+                let mut name = format!("L{}", target);
+                while used.contains(&name) {
+                    name.insert(0, '_');
+                }
+                used.insert(name.clone());
+                name
+            });
+        }
+    }
+
+    names
+}
+
+/// Data can only be written back out if it sits where the
+/// assembler would put it, so anything else is left as a
+/// comment.
+fn write_data(output: &mut String, segments: &[(u16, &[u8])]) {
+    let mut next_address = DATA_START as usize;
+
+    for (address, bytes) in segments {
+        if *address as usize != next_address {
+            writeln!(
+                output,
+                "// {} bytes of data at {:#06x} can't be written as assembly.",
+                bytes.len(), address
+            ).unwrap();
+            continue;
+        }
+
+        for chunk in bytes.chunks(BYTES_PER_LINE) {
+            let values: Vec<_> = chunk.iter().map(|byte| byte.to_string()).collect();
+            writeln!(output, "    .byte {};", values.join(", ")).unwrap();
+        }
+        next_address += bytes.len();
+    }
+}
+
+/// Whether `name` can be written after an `@`.
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {},
+        _ => return false,
+    }
+    name != "_" && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Assembles `source`, disassembles it, and checks that
+    /// assembling the result gives the same program.
+    fn round_trip(source: &str) {
+        let program = crate::assemble(source).unwrap();
+        let text = disassemble(&program);
+        let reassembled = crate::assemble(&text)
+            .unwrap_or_else(|err| panic!("{}\n{}", err, text));
+        assert_eq!(reassembled, program, "{}", text);
+    }
+
+    #[test]
+    fn code_round_trips() {
+        round_trip("@start PUT $ax, 3; @loop SUBI $ax, 1; JNZ @loop; CALL @f; EXIT; @f RETURN;");
+    }
+
+    #[test]
+    fn data_round_trips() {
+        round_trip(".byte 1, 2, 3; .string \"hello\"; LOAD $ax, 0x4000; EXIT;");
+    }
+
+    #[test]
+    fn labels_past_the_end_round_trip() {
+        round_trip("PUT $ax, 0; JZ @end; EXIT; @end");
+    }
+
+    #[test]
+    fn labels_past_the_end_round_trip_with_data() {
+        round_trip(".byte 5; JZ @end; EXIT; @end");
+    }
+
+    /// Like `round_trip`, through an image loaded at `load_address`.
+    fn image_round_trip(source: &str, load_address: u16) {
+        let image = crate::assemble_to_image(source, load_address).unwrap();
+        let text = disassemble_image(&image, load_address, &HashMap::new())
+            .unwrap_or_else(|err| panic!("{}\n{}", err, source));
+        let reassembled = crate::assemble_to_image(&text, load_address)
+            .unwrap_or_else(|err| panic!("{}\n{}", err, text));
+        assert_eq!(reassembled, image, "{}", text);
+    }
+
+    #[test]
+    fn images_round_trip() {
+        image_round_trip("@top SUBI $ax, 1; JNZ @top; JZ @end; EXIT; @end", 0x100);
+    }
+
+    #[test]
+    fn images_with_data_round_trip() {
+        let source = "@value .byte 7, 0; .string \"hi\"; LOAD $ax, @value; PUT $bx, 0; JZ @end; EXIT; @end";
+        image_round_trip(source, 0x100);
+        image_round_trip(source, 0);
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+
+/// One problem found while assembling, and where.
+/// `line` and `column` both count from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RocAsmDiagnostic {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl RocAsmDiagnostic {
+    pub(crate) fn from_span(span: proc_macro2::Span, message: String) -> Self {
+        let start = span.start();
+        Self {
+            message,
+            line: start.line,
+            column: start.column + 1,
+        }
+    }
+}
+
+impl fmt::Display for RocAsmDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+
+/// Something suspicious in a program that still
+/// assembled, such as a label nothing uses.
+#[derive(Clone, Debug)]
+pub struct RocAsmWarning {
+    pub message: String,
+    pub span: proc_macro2::Span,
+}
+
+impl RocAsmWarning {
+    pub(crate) fn new(span: proc_macro2::Span, message: String) -> Self {
+        Self { message, span }
+    }
+
+    /// This warning, with its span turned into a line and column.
+    pub fn to_diagnostic(&self) -> RocAsmDiagnostic {
+        RocAsmDiagnostic::from_span(self.span, self.message.clone())
+    }
+}
+
+
+#[derive(Debug)]
+pub enum RocAsmError {
+    Io(std::io::Error),
+
+    /// The source didn't assemble. There is always
+    /// at least one diagnostic.
+    Assembly(Vec<RocAsmDiagnostic>),
+}
+
+impl fmt::Display for RocAsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Assembly(diagnostics) => {
+                for (i, diagnostic) in diagnostics.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", diagnostic)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl std::error::Error for RocAsmError {}
+
+impl From<std::io::Error> for RocAsmError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<syn::Error> for RocAsmError {
+    fn from(err: syn::Error) -> Self {
+        let diagnostics = err.into_iter()
+            .map(|e| RocAsmDiagnostic::from_span(e.span(), e.to_string()))
+            .collect();
+        Self::Assembly(diagnostics)
+    }
+}
+
+impl From<proc_macro2::LexError> for RocAsmError {
+    fn from(err: proc_macro2::LexError) -> Self {
+        Self::Assembly(vec![
+            RocAsmDiagnostic::from_span(err.span(), "Could not read the source as tokens.".to_string())
+        ])
+    }
+}
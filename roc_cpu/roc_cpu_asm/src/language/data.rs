@@ -0,0 +1,222 @@
+use std::path::Path;
+
+use roc_cpu_types::RocCPUDataSegment;
+use syn::parse::ParseStream;
+use syn::*;
+
+use super::{push_error, RocAsmExpr, RocAsmLabel, RocAsmSymbols};
+
+/// Where the assembler starts placing data. This sits
+/// below display memory, and well clear of code loaded
+/// at address 0.
+pub const DATA_START: u16 = 0x4000;
+
+const MEMORY_SIZE: usize = 0x10000;
+
+/// The address and length of each data entry, in order.
+pub type RocAsmDataLayout = Vec<(u16, usize)>;
+
+
+/// The body of a data directive.
+#[derive(Clone)]
+pub enum RocAsmData {
+    /// `.byte 1, 2, 3;`
+    Bytes(Vec<RocAsmExpr>),
+    /// `.word 0x1234, 5;`, each stored `hi, lo`.
+    Words(Vec<RocAsmExpr>),
+    /// `.string "text\n";`, with no terminator
+    /// unless one is written.
+    String(Vec<u8>),
+    /// `.fill count;` or `.fill count, value;`
+    Fill { count: RocAsmExpr, value: Option<RocAsmExpr> },
+    /// `.incbin "path";`
+    IncBin(LitStr),
+}
+
+/// A data directive, and the labels written just before it.
+#[derive(Clone)]
+pub struct RocAsmDataEntry {
+    pub labels: Vec<RocAsmLabel>,
+    pub data: RocAsmData,
+
+    /// Where the directive was written.
+    pub span: proc_macro2::Span,
+}
+
+impl RocAsmData {
+    /// Every label used in this directive's values.
+    pub fn labels_mut<'a>(&'a mut self, labels: &mut Vec<&'a mut RocAsmLabel>) {
+        match self {
+            Self::Bytes(values) | Self::Words(values) => {
+                for value in values {
+                    value.labels_mut(labels);
+                }
+            },
+            Self::Fill { count, value } => {
+                count.labels_mut(labels);
+                if let Some(value) = value {
+                    value.labels_mut(labels);
+                }
+            },
+            Self::String(_) | Self::IncBin(_) => {},
+        }
+    }
+
+    /// Parses the rest of a `.byte`, `.word`, `.string`,
+    /// `.fill` or `.incbin` directive, or returns `None`
+    /// when `directive` is none of them.
+    pub fn parse_directive(directive: &Ident, input: ParseStream) -> Result<Option<Self>> {
+        let data = match directive.to_string().as_str() {
+            "byte" => Self::Bytes(parse_list(input)?),
+            "word" => Self::Words(parse_list(input)?),
+            "string" => {
+                if input.peek(LitByteStr) {
+                    Self::String(input.parse::<LitByteStr>()?.value())
+                } else {
+                    Self::String(input.parse::<LitStr>()?.value().into_bytes())
+                }
+            },
+            "fill" => {
+                let count: RocAsmExpr = input.parse()?;
+                let value = if input.peek(Token![,]) {
+                    input.parse::<Token![,]>()?;
+                    Some(input.parse()?)
+                } else {
+                    None
+                };
+                Self::Fill { count, value }
+            },
+            "incbin" => Self::IncBin(input.parse()?),
+            _ => return Ok(None),
+        };
+        Ok(Some(data))
+    }
+}
+
+fn parse_list(input: ParseStream) -> Result<Vec<RocAsmExpr>> {
+    let mut values = vec![];
+    loop {
+        values.push(input.parse()?);
+        if !input.peek(Token![,]) {
+            break;
+        }
+        input.parse::<Token![,]>()?;
+    }
+    Ok(values)
+}
+
+
+/// Gives every data entry an address from `DATA_START` up,
+/// adds their labels to `symbols`, then works out their
+/// bytes. Paths in `.incbin` are relative to `base_dir`.
+///
+/// Alongside the data comes where each entry ended up.
+pub fn lay_out_data(
+    entries: &[RocAsmDataEntry],
+    symbols: &mut RocAsmSymbols,
+    base_dir: &Path
+) -> Result<Option<(RocCPUDataSegment, RocAsmDataLayout)>> {
+    if entries.is_empty() {
+        return Ok(None);
+    }
+
+    // Sizes (and any included files) first, so that every
+    // data label is known before values that use them.
+    let mut errors = None;
+    let mut included = Vec::with_capacity(entries.len());
+    let mut layout = Vec::with_capacity(entries.len());
+    let mut address = DATA_START as usize;
+
+    for entry in entries {
+        let mut bytes = None;
+        let size = match &entry.data {
+            RocAsmData::Bytes(values) => Ok(values.len()),
+            RocAsmData::Words(values) => Ok(values.len() * 2),
+            RocAsmData::String(string) => Ok(string.len()),
+            RocAsmData::Fill { count, .. } => fill_count(count, symbols),
+            RocAsmData::IncBin(path) => read_incbin(path, base_dir).map(|file| {
+                let size = file.len();
+                bytes = Some(file);
+                size
+            }),
+        };
+        included.push(bytes);
+
+        for label in &entry.labels {
+            symbols.data_labels.insert(label.name.clone(), address.min(u16::MAX as usize) as u16);
+        }
+
+        match size {
+            Ok(size) => {
+                layout.push((address as u16, size));
+                address += size;
+            },
+            Err(err) => push_error(&mut errors, err),
+        }
+        if address > MEMORY_SIZE {
+            let span = entry.labels.first().map_or_else(proc_macro2::Span::call_site, |label| label.span);
+            return Err(Error::new(span, "The program's data does not fit in memory."));
+        }
+    }
+
+    if let Some(err) = errors {
+        return Err(err);
+    }
+
+    let mut output = vec![];
+    for (entry, file) in entries.iter().zip(included) {
+        let result = match &entry.data {
+            RocAsmData::Bytes(values) => values.iter().try_for_each(|value| {
+                output.push(value.evaluate_byte(symbols)?);
+                Ok(())
+            }),
+            RocAsmData::Words(values) => values.iter().try_for_each(|value| {
+                output.extend(value.evaluate_word(symbols)?.to_be_bytes());
+                Ok(())
+            }),
+            RocAsmData::String(string) => {
+                output.extend(string);
+                Ok(())
+            },
+            RocAsmData::Fill { count, value } => {
+                let value = match value {
+                    Some(value) => value.evaluate_byte(symbols),
+                    None => Ok(0),
+                };
+                fill_count(count, symbols).and_then(|count| {
+                    output.extend(std::iter::repeat_n(value?, count));
+                    Ok(())
+                })
+            },
+            RocAsmData::IncBin(_) => {
+                output.extend(file.unwrap_or_default());
+                Ok(())
+            },
+        };
+
+        if let Err(err) = result {
+            push_error(&mut errors, err);
+        }
+    }
+
+    match errors {
+        Some(err) => Err(err),
+        None => Ok(Some((RocCPUDataSegment { address: DATA_START, bytes: output }, layout))),
+    }
+}
+
+fn fill_count(count: &RocAsmExpr, symbols: &RocAsmSymbols) -> Result<usize> {
+    let value = count.evaluate(symbols)?;
+    if !(0..=MEMORY_SIZE as i64).contains(&value) {
+        return Err(count.error(format!("Can't fill {} bytes.", value)));
+    }
+    Ok(value as usize)
+}
+
+fn read_incbin(path: &LitStr, base_dir: &Path) -> Result<Vec<u8>> {
+    let full_path = base_dir.join(path.value());
+    std::fs::read(&full_path).map_err(|err| Error::new(
+        path.span(),
+        format!("Could not read \"{}\": {}", full_path.display(), err)
+    ))
+}
@@ -0,0 +1,420 @@
+use proc_macro2::{Span, TokenStream};
+use syn::parse::ParseStream;
+use syn::*;
+
+use super::{RocAsmLabel, RocAsmSymbols};
+
+/// A constant expression, such as `0x40 + 2` or `lo(1000)`,
+/// worked out when the program is assembled.
+#[derive(Clone)]
+pub struct RocAsmExpr {
+    kind: RocAsmExprKind,
+
+    /// The tokens this expression was written as, so
+    /// errors can underline all of it.
+    tokens: TokenStream,
+}
+
+#[derive(Clone)]
+enum RocAsmExprKind {
+    Number(i64),
+    Constant(Ident),
+    Label(RocAsmLabel),
+    Negate(Box<RocAsmExpr>),
+    Binary(RocAsmBinaryOp, Box<RocAsmExpr>, Box<RocAsmExpr>),
+    Lo(Box<RocAsmExpr>),
+    Hi(Box<RocAsmExpr>),
+}
+
+/// What an evaluation needs to carry along.
+#[derive(Default)]
+struct Evaluation {
+    /// The constants being worked out, so a
+    /// cycle is an error rather than a hang.
+    resolving: Vec<String>,
+
+    /// Whether labels mean instruction indexes,
+    /// rather than data addresses.
+    code_labels: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RocAsmBinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    ShiftLeft,
+    ShiftRight,
+    And,
+    Or,
+}
+
+impl RocAsmBinaryOp {
+    /// Higher binds tighter, in the same order as Rust.
+    fn precedence(self) -> u8 {
+        match self {
+            Self::Mul | Self::Div => 5,
+            Self::Add | Self::Sub => 4,
+            Self::ShiftLeft | Self::ShiftRight => 3,
+            Self::And => 2,
+            Self::Or => 1,
+        }
+    }
+
+    fn peek(input: ParseStream) -> Option<Self> {
+        // The two character operators have to be
+        // checked before their one character prefixes.
+        if input.peek(Token![<<]) {
+            Some(Self::ShiftLeft)
+        } else if input.peek(Token![>>]) {
+            Some(Self::ShiftRight)
+        } else if input.peek(Token![+]) {
+            Some(Self::Add)
+        } else if input.peek(Token![-]) {
+            Some(Self::Sub)
+        } else if input.peek(Token![*]) {
+            Some(Self::Mul)
+        } else if input.peek(Token![/]) {
+            Some(Self::Div)
+        } else if input.peek(Token![&]) && !input.peek(Token![&&]) {
+            Some(Self::And)
+        } else if input.peek(Token![|]) && !input.peek(Token![||]) {
+            Some(Self::Or)
+        } else {
+            None
+        }
+    }
+
+    fn consume(self, input: ParseStream) -> Result<()> {
+        match self {
+            Self::Add => { input.parse::<Token![+]>()?; },
+            Self::Sub => { input.parse::<Token![-]>()?; },
+            Self::Mul => { input.parse::<Token![*]>()?; },
+            Self::Div => { input.parse::<Token![/]>()?; },
+            Self::ShiftLeft => { input.parse::<Token![<<]>()?; },
+            Self::ShiftRight => { input.parse::<Token![>>]>()?; },
+            Self::And => { input.parse::<Token![&]>()?; },
+            Self::Or => { input.parse::<Token![|]>()?; },
+        }
+        Ok(())
+    }
+}
+
+
+impl RocAsmExpr {
+    /// Whether the next tokens could start an expression.
+    pub fn peek(input: ParseStream) -> bool {
+        input.peek(LitInt)
+            || input.peek(LitChar)
+            || input.peek(LitByte)
+            || input.peek(Token![-])
+            || input.peek(Token![@])
+            || input.peek(token::Paren)
+            || input.peek(Ident)
+    }
+
+    /// Where this expression starts, for errors.
+    pub fn span(&self) -> Span {
+        self.tokens.clone().into_iter().next()
+            .map(|token| token.span())
+            .unwrap_or_else(Span::call_site)
+    }
+
+    /// An error that underlines the whole expression.
+    pub fn error(&self, message: impl std::fmt::Display) -> Error {
+        Error::new_spanned(&self.tokens, message)
+    }
+
+    /// Works out the value of this expression.
+    pub fn evaluate(&self, symbols: &RocAsmSymbols) -> Result<i64> {
+        self.evaluate_in(symbols, &mut Evaluation::default())
+    }
+
+    /// Works out this expression as a jump target, where
+    /// labels stand for the index of their instruction.
+    pub fn evaluate_jump_target(&self, symbols: &RocAsmSymbols) -> Result<u16> {
+        let mut evaluation = Evaluation { code_labels: true, ..Default::default() };
+        let value = self.evaluate_in(symbols, &mut evaluation)?;
+        if !(0..=0xFFFF).contains(&value) {
+            return Err(self.error(format!(
+                "{} is not an instruction that can be jumped to.", value
+            )));
+        }
+        Ok(value as u16)
+    }
+
+    /// Every label used in this expression.
+    pub fn labels_mut<'a>(&'a mut self, labels: &mut Vec<&'a mut RocAsmLabel>) {
+        match &mut self.kind {
+            RocAsmExprKind::Label(label) => labels.push(label),
+            RocAsmExprKind::Negate(expr)
+            | RocAsmExprKind::Lo(expr)
+            | RocAsmExprKind::Hi(expr) => expr.labels_mut(labels),
+            RocAsmExprKind::Binary(_, lhs, rhs) => {
+                lhs.labels_mut(labels);
+                rhs.labels_mut(labels);
+            },
+            RocAsmExprKind::Number(_) | RocAsmExprKind::Constant(_) => {},
+        }
+    }
+
+    fn evaluate_in(&self, symbols: &RocAsmSymbols, evaluation: &mut Evaluation) -> Result<i64> {
+        match &self.kind {
+            RocAsmExprKind::Number(n) => Ok(*n),
+            RocAsmExprKind::Constant(name) => {
+                let key = name.to_string();
+                let Some(value) = symbols.constants.get(&key) else {
+                    return Err(Error::new(
+                        name.span(),
+                        format!("Constant \"{}\" is not defined in this program.", name)
+                    ));
+                };
+                if evaluation.resolving.contains(&key) {
+                    return Err(Error::new(
+                        name.span(),
+                        format!("Constant \"{}\" is defined in terms of itself.", name)
+                    ));
+                }
+
+                evaluation.resolving.push(key);
+                let result = value.evaluate_in(symbols, evaluation);
+                evaluation.resolving.pop();
+                result
+            },
+            RocAsmExprKind::Label(label) => {
+                if evaluation.code_labels {
+                    return match symbols.labels.get(&label.name) {
+                        Some(index) => Ok(*index as i64),
+                        None if symbols.data_labels.contains_key(&label.name) => Err(Error::new(
+                            label.span,
+                            format!("Label \"{}\" marks data, and can't be jumped to.", label)
+                        )),
+                        None => Err(label.undefined()),
+                    };
+                }
+
+                match symbols.data_labels.get(&label.name) {
+                    Some(address) => Ok(*address as i64),
+                    None if symbols.labels.contains_key(&label.name) => Err(Error::new(
+                        label.span,
+                        format!("Label \"{}\" marks an instruction, only data labels can be used as values.", label)
+                    )),
+                    None => Err(label.undefined()),
+                }
+            },
+            RocAsmExprKind::Negate(expr) => {
+                expr.evaluate_in(symbols, evaluation)?
+                    .checked_neg()
+                    .ok_or_else(|| self.error("This expression overflows."))
+            },
+            RocAsmExprKind::Lo(expr) => Ok(expr.evaluate_address_in(symbols, evaluation)? & 0xFF),
+            RocAsmExprKind::Hi(expr) => Ok(expr.evaluate_address_in(symbols, evaluation)? >> 8),
+            RocAsmExprKind::Binary(op, lhs, rhs) => {
+                let lhs = lhs.evaluate_in(symbols, evaluation)?;
+                let right = rhs.evaluate_in(symbols, evaluation)?;
+
+                let result = match op {
+                    RocAsmBinaryOp::Add => lhs.checked_add(right),
+                    RocAsmBinaryOp::Sub => lhs.checked_sub(right),
+                    RocAsmBinaryOp::Mul => lhs.checked_mul(right),
+                    RocAsmBinaryOp::Div => {
+                        if right == 0 {
+                            return Err(rhs.error("Division by zero in a constant expression."));
+                        }
+                        lhs.checked_div(right)
+                    },
+                    RocAsmBinaryOp::ShiftLeft | RocAsmBinaryOp::ShiftRight => {
+                        if !(0..64).contains(&right) {
+                            return Err(rhs.error(format!("Can't shift by {}.", right)));
+                        }
+                        if *op == RocAsmBinaryOp::ShiftLeft {
+                            lhs.checked_shl(right as u32)
+                        } else {
+                            lhs.checked_shr(right as u32)
+                        }
+                    },
+                    RocAsmBinaryOp::And => Some(lhs & right),
+                    RocAsmBinaryOp::Or => Some(lhs | right),
+                };
+
+                result.ok_or_else(|| self.error("This expression overflows."))
+            },
+        }
+    }
+
+    /// Works out this expression as a byte. Negative values
+    /// down to -128 are stored as two's complement.
+    pub fn evaluate_byte(&self, symbols: &RocAsmSymbols) -> Result<u8> {
+        let value = self.evaluate(symbols)?;
+        if !(-128..=255).contains(&value) {
+            return Err(self.error(format!(
+                "{} does not fit in a byte (-128 to 255).", value
+            )));
+        }
+        Ok(value as u8)
+    }
+
+    /// Works out this expression as a 16 bit word. Negative
+    /// values down to -32768 are stored as two's complement.
+    pub fn evaluate_word(&self, symbols: &RocAsmSymbols) -> Result<u16> {
+        let value = self.evaluate(symbols)?;
+        if !(-32768..=65535).contains(&value) {
+            return Err(self.error(format!(
+                "{} does not fit in a word (-32768 to 65535).", value
+            )));
+        }
+        Ok(value as u16)
+    }
+
+    /// Works out this expression as a 16 bit address.
+    pub fn evaluate_address(&self, symbols: &RocAsmSymbols) -> Result<i64> {
+        self.evaluate_address_in(symbols, &mut Evaluation::default())
+    }
+
+    fn evaluate_address_in(&self, symbols: &RocAsmSymbols, evaluation: &mut Evaluation) -> Result<i64> {
+        let value = self.evaluate_in(symbols, evaluation)?;
+        if !(0..=0xFFFF).contains(&value) {
+            return Err(self.error(format!(
+                "{} does not fit in an address (0 to 65535).", value
+            )));
+        }
+        Ok(value)
+    }
+
+
+    // PARSING //
+
+    fn parse_binary(input: ParseStream, min_precedence: u8) -> Result<Self> {
+        let begin = input.cursor();
+        let mut lhs = Self::parse_unary(input)?;
+
+        while let Some(op) = RocAsmBinaryOp::peek(input) {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            op.consume(input)?;
+            let rhs = Self::parse_binary(input, op.precedence() + 1)?;
+
+            lhs = Self {
+                kind: RocAsmExprKind::Binary(op, Box::new(lhs), Box::new(rhs)),
+                tokens: tokens_between(begin, input),
+            };
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary(input: ParseStream) -> Result<Self> {
+        let begin = input.cursor();
+
+        if input.peek(Token![-]) {
+            input.parse::<Token![-]>()?;
+            let expr = Self::parse_unary(input)?;
+            return Ok(Self {
+                kind: RocAsmExprKind::Negate(Box::new(expr)),
+                tokens: tokens_between(begin, input),
+            });
+        }
+
+        let kind = Self::parse_atom(input)?;
+        Ok(Self { kind, tokens: tokens_between(begin, input) })
+    }
+
+    fn parse_atom(input: ParseStream) -> Result<RocAsmExprKind> {
+        let lookahead = input.lookahead1();
+
+        if lookahead.peek(LitInt) {
+            let lit: LitInt = input.parse()?;
+            if !lit.suffix().is_empty() {
+                return Err(Error::new(
+                    lit.span(),
+                    format!("Numbers can't have a type suffix, found \"{}\".", lit.suffix())
+                ));
+            }
+            let n = lit.base10_parse::<i64>().map_err(|_| Error::new(
+                lit.span(),
+                format!("{} is far too large.", lit)
+            ))?;
+            return Ok(RocAsmExprKind::Number(n));
+        }
+
+        if lookahead.peek(LitChar) {
+            let lit: LitChar = input.parse()?;
+            let c = lit.value();
+            if !c.is_ascii() {
+                return Err(Error::new(
+                    lit.span(),
+                    format!("'{}' is not an ASCII character.", c)
+                ));
+            }
+            return Ok(RocAsmExprKind::Number(c as i64));
+        }
+
+        if lookahead.peek(LitByte) {
+            let lit: LitByte = input.parse()?;
+            return Ok(RocAsmExprKind::Number(lit.value() as i64));
+        }
+
+        if lookahead.peek(Token![@]) {
+            return Ok(RocAsmExprKind::Label(input.parse()?));
+        }
+
+        if lookahead.peek(token::Paren) {
+            let content;
+            parenthesized!(content in input);
+            let expr: Self = content.parse()?;
+            return Ok(expr.kind);
+        }
+
+        if lookahead.peek(Ident) {
+            let func: Ident = input.parse()?;
+            if !input.peek(token::Paren) {
+                // A name on its own is a .const
+                return Ok(RocAsmExprKind::Constant(func));
+            }
+
+            let content;
+            parenthesized!(content in input);
+            let arg: Self = content.parse()?;
+
+            return match func.to_string().as_str() {
+                "lo" => Ok(RocAsmExprKind::Lo(Box::new(arg))),
+                "hi" => Ok(RocAsmExprKind::Hi(Box::new(arg))),
+                _ => Err(Error::new(
+                    func.span(),
+                    format!("\"{}\" is not a known function, only lo() and hi() are.", func)
+                )),
+            };
+        }
+
+        Err(lookahead.error())
+    }
+}
+
+impl syn::parse::Parse for RocAsmExpr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let expr = Self::parse_binary(input, 0)?;
+        if !input.is_empty() && !input.peek(Token![,]) && !input.peek(Token![;]) {
+            return Err(input.error("Expected an operator, `,` or `;` here."));
+        }
+        Ok(expr)
+    }
+}
+
+/// Every token from `begin` up to where `input` is now.
+fn tokens_between(begin: syn::buffer::Cursor, input: ParseStream) -> TokenStream {
+    let end = input.cursor();
+    let mut tokens = TokenStream::new();
+    let mut cursor = begin;
+    while cursor != end {
+        match cursor.token_tree() {
+            Some((token, next)) => {
+                tokens.extend([token]);
+                cursor = next;
+            },
+            None => break,
+        }
+    }
+    tokens
+}
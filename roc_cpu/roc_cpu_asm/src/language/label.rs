@@ -0,0 +1,122 @@
+use std::fmt;
+
+use proc_macro2::Span;
+use syn::ext::IdentExt;
+use syn::parse::ParseStream;
+use syn::*;
+
+/// The ways a label can be written.
+#[derive(Clone, PartialEq, Eq)]
+pub enum RocAsmLabelKind {
+    /// `@name`, which also starts a new scope
+    /// for local labels when it's defined.
+    Global(String),
+    /// `@.name`, scoped to the global label before it.
+    Local(String),
+    /// `@@`, which can only be defined.
+    Anonymous,
+    /// `@+`, `@++`, ... the first, second, ...
+    /// anonymous label after this point.
+    Forward(usize),
+    /// `@-`, `@--`, ... the first, second, ...
+    /// anonymous label before this point.
+    Backward(usize),
+}
+
+/// A label, either where it's defined or where it's used.
+#[derive(Clone)]
+pub struct RocAsmLabel {
+    pub kind: RocAsmLabelKind,
+
+    /// The name it's stored under in `RocAsmSymbols`,
+    /// once `Program` has worked out its scope.
+    pub name: String,
+
+    pub span: Span,
+}
+
+impl RocAsmLabel {
+    /// Works out the full name of this label. `scope` is the
+    /// global label it's under, and `anonymous` counts the
+    /// anonymous labels defined before it.
+    pub fn resolve(&mut self, scope: &str, anonymous: usize) {
+        self.name = match &self.kind {
+            RocAsmLabelKind::Global(name) => name.clone(),
+            RocAsmLabelKind::Local(name) => format!("{}.{}", scope, name),
+            RocAsmLabelKind::Anonymous => format!("@{}", anonymous),
+            RocAsmLabelKind::Forward(n) => format!("@{}", anonymous + n - 1),
+            RocAsmLabelKind::Backward(n) => match anonymous.checked_sub(*n) {
+                Some(index) => format!("@{}", index),
+                None => String::new(),
+            },
+        };
+    }
+
+    /// The error for using this label when it isn't defined.
+    pub fn undefined(&self) -> Error {
+        let message = match self.kind {
+            RocAsmLabelKind::Forward(_) => format!("There is no anonymous label for \"{}\" to find after it.", self),
+            RocAsmLabelKind::Backward(_) => format!("There is no anonymous label for \"{}\" to find before it.", self),
+            _ => format!("Label \"{}\" is not defined in this program.", self),
+        };
+        Error::new(self.span, message)
+    }
+}
+
+impl fmt::Display for RocAsmLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            RocAsmLabelKind::Global(name) => write!(f, "@{}", name),
+            RocAsmLabelKind::Local(name) => write!(f, "@.{}", name),
+            RocAsmLabelKind::Anonymous => write!(f, "@@"),
+            RocAsmLabelKind::Forward(n) => write!(f, "@{}", "+".repeat(*n)),
+            RocAsmLabelKind::Backward(n) => write!(f, "@{}", "-".repeat(*n)),
+        }
+    }
+}
+
+impl syn::parse::Parse for RocAsmLabel {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let at = input.parse::<Token![@]>()?;
+
+        let kind = if input.peek(Token![@]) {
+            input.parse::<Token![@]>()?;
+            RocAsmLabelKind::Anonymous
+        } else if input.peek(Token![.]) {
+            input.parse::<Token![.]>()?;
+            RocAsmLabelKind::Local(Ident::parse_any(input)?.to_string())
+        } else if input.peek(Token![+]) {
+            RocAsmLabelKind::Forward(count_repeats(input, '+')?)
+        } else if input.peek(Token![-]) {
+            RocAsmLabelKind::Backward(count_repeats(input, '-')?)
+        } else {
+            RocAsmLabelKind::Global(Ident::parse_any(input)?.to_string())
+        };
+
+        let name = match &kind {
+            RocAsmLabelKind::Global(name) => name.clone(),
+            _ => String::new(),
+        };
+
+        Ok(Self { kind, name, span: at.span })
+    }
+}
+
+/// Consumes a run of `c` characters, such as `++`.
+fn count_repeats(input: ParseStream, c: char) -> Result<usize> {
+    input.step(|cursor| {
+        let mut count = 0;
+        let mut rest = *cursor;
+        while let Some((punct, next)) = rest.punct() {
+            if punct.as_char() != c {
+                break;
+            }
+            count += 1;
+            rest = next;
+            if punct.spacing() == proc_macro2::Spacing::Alone {
+                break;
+            }
+        }
+        Ok((count, rest))
+    })
+}
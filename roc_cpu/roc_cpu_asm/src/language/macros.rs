@@ -0,0 +1,352 @@
+use std::collections::{HashMap, HashSet};
+
+use proc_macro2::{Group, Ident, Span, TokenStream, TokenTree};
+use syn::{Error, Result};
+
+use super::push_error;
+
+/// How deep macros may expand inside other macros
+/// before we assume they recurse forever.
+pub const MACRO_DEPTH_LIMIT: usize = 64;
+
+/// How many macro calls a whole program may expand,
+/// so that macros calling each other several times
+/// can't blow up exponentially.
+pub const MACRO_EXPANSION_LIMIT: usize = 10_000;
+
+
+/// A `.macro name a, b; ... .endm;` definition.
+struct RocAsmMacro {
+    params: Vec<String>,
+    body: Vec<TokenTree>,
+
+    /// Labels defined in the body, which are renamed
+    /// in every expansion so they don't clash.
+    local_labels: HashSet<String>,
+}
+
+/// Replaces every `.macro` definition with nothing, and
+/// every call with the macro's body, handing back the
+/// names given to labels defined in a body, and the
+/// errors found along the way.
+///
+/// Macros must be defined before they are called. Tokens
+/// from a macro body take the span of the call, so errors
+/// in an expansion point at the line that used it.
+pub fn expand_macros(tokens: TokenStream) -> (TokenStream, HashSet<String>, Option<Error>) {
+    let mut expander = MacroExpander {
+        macros: HashMap::new(),
+        labels: HashSet::new(),
+        expansions: 0,
+        exhausted: false,
+        errors: None,
+    };
+
+    let tokens = tokens.into_iter().collect();
+    let output = expander.expand(tokens, 0);
+    (output.into_iter().collect(), expander.labels, expander.errors)
+}
+
+struct MacroExpander {
+    macros: HashMap<String, RocAsmMacro>,
+
+    /// What every macro body label has been renamed to.
+    labels: HashSet<String>,
+
+    expansions: usize,
+
+    /// Set once `MACRO_EXPANSION_LIMIT` is reached, after
+    /// which calls are dropped without more errors.
+    exhausted: bool,
+    errors: Option<Error>,
+}
+
+impl MacroExpander {
+    fn expand(&mut self, tokens: Vec<TokenTree>, depth: usize) -> Vec<TokenTree> {
+        let mut output = vec![];
+        let mut statements = split_statements(tokens).into_iter();
+
+        while let Some(statement) = statements.next() {
+            if let Some(dot) = directive(&statement, "macro") {
+                if depth > 0 {
+                    push_error(&mut self.errors, Error::new(dot, "Macros can't be defined inside other macros."));
+                }
+                self.define(statement, &mut statements);
+                continue;
+            }
+
+            if let Some(dot) = directive(&statement, "endm") {
+                push_error(&mut self.errors, Error::new(dot, ".endm without a .macro before it."));
+                continue;
+            }
+
+            let labels = leading_labels(&statement);
+            let call = match statement.get(labels) {
+                Some(TokenTree::Ident(name)) if self.macros.contains_key(&name.to_string()) => name.clone(),
+                _ => {
+                    output.extend(statement);
+                    continue;
+                }
+            };
+
+            output.extend(statement[..labels].iter().cloned());
+
+            if depth >= MACRO_DEPTH_LIMIT {
+                push_error(&mut self.errors, Error::new(
+                    call.span(),
+                    format!("Macro \"{}\" expands more than {} levels deep.", call, MACRO_DEPTH_LIMIT)
+                ));
+                continue;
+            }
+
+            if self.expansions >= MACRO_EXPANSION_LIMIT {
+                if !self.exhausted {
+                    self.exhausted = true;
+                    push_error(&mut self.errors, Error::new(
+                        call.span(),
+                        format!("Macros expand more than {} times in total.", MACRO_EXPANSION_LIMIT)
+                    ));
+                }
+                continue;
+            }
+
+            let args = split_arguments(&statement[labels + 1..]);
+            match self.instantiate(&call, args) {
+                Ok(body) => {
+                    let expanded = self.expand(body, depth + 1);
+                    output.extend(expanded);
+                },
+                Err(err) => push_error(&mut self.errors, err),
+            }
+        }
+
+        output
+    }
+
+    /// Reads a macro definition, up to and including its `.endm`.
+    fn define(&mut self, header: Vec<TokenTree>, rest: &mut impl Iterator<Item = Vec<TokenTree>>) {
+        let mut body = vec![];
+        let mut local_labels = HashSet::new();
+        let mut ended = false;
+
+        for statement in rest.by_ref() {
+            if directive(&statement, "endm").is_some() {
+                ended = true;
+                break;
+            }
+            if let Some(dot) = directive(&statement, "macro") {
+                push_error(&mut self.errors, Error::new(dot, "Macros can't be defined inside other macros."));
+            }
+
+            for label in statement[..leading_labels(&statement)].iter() {
+                if let TokenTree::Ident(label) = label {
+                    local_labels.insert(label.to_string());
+                }
+            }
+            body.extend(statement);
+        }
+
+        // `.` `macro` name params... `;`
+        let name = match header.get(2) {
+            Some(TokenTree::Ident(name)) => name.clone(),
+            other => {
+                let span = other.map_or_else(|| header[1].span(), |token| token.span());
+                push_error(&mut self.errors, Error::new(span, ".macro expects a name here."));
+                return;
+            }
+        };
+
+        if !ended {
+            push_error(&mut self.errors, Error::new(
+                name.span(),
+                format!("Macro \"{}\" is missing its .endm.", name)
+            ));
+            return;
+        }
+
+        let mut params = vec![];
+        for param in split_arguments(&header[3..]) {
+            match param.as_slice() {
+                [TokenTree::Ident(param)] => params.push(param.to_string()),
+                [] => {},
+                other => {
+                    push_error(&mut self.errors, Error::new(
+                        other[0].span(),
+                        "Macro parameters must be plain names."
+                    ));
+                    return;
+                }
+            }
+        }
+
+        if self.macros.contains_key(&name.to_string()) {
+            push_error(&mut self.errors, Error::new(
+                name.span(),
+                format!("Macro \"{}\" is already defined.", name)
+            ));
+            return;
+        }
+
+        self.macros.insert(name.to_string(), RocAsmMacro { params, body, local_labels });
+    }
+
+    /// The body of `call`'s macro, with the arguments
+    /// substituted in and its local labels renamed.
+    fn instantiate(&mut self, call: &Ident, args: Vec<Vec<TokenTree>>) -> Result<Vec<TokenTree>> {
+        let definition = &self.macros[&call.to_string()];
+
+        // A call with no arguments splits into one empty one.
+        let args: Vec<_> = args.into_iter().filter(|arg| !arg.is_empty()).collect();
+        if args.len() != definition.params.len() {
+            return Err(Error::new(
+                call.span(),
+                format!(
+                    "Macro \"{}\" takes {} arguments, but {} were given.",
+                    call, definition.params.len(), args.len()
+                )
+            ));
+        }
+
+        self.expansions += 1;
+        let substitution = Substitution {
+            params: definition.params.iter().cloned().zip(args).collect(),
+            local_labels: &definition.local_labels,
+            suffix: format!("{}_{}", call, self.expansions),
+            span: call.span(),
+        };
+        self.labels.extend(definition.local_labels.iter().map(|label| substitution.rename(label)));
+
+        Ok(substitution.apply(&definition.body))
+    }
+}
+
+
+struct Substitution<'a> {
+    params: HashMap<String, Vec<TokenTree>>,
+    local_labels: &'a HashSet<String>,
+    suffix: String,
+    span: Span,
+}
+
+impl Substitution<'_> {
+    /// The name `label` is given in this expansion.
+    fn rename(&self, label: &str) -> String {
+        format!("__{}_{}", label, self.suffix)
+    }
+
+    fn apply(&self, tokens: &[TokenTree]) -> Vec<TokenTree> {
+        let mut output = vec![];
+        let mut after_at = false;
+
+        for token in tokens {
+            match token {
+                TokenTree::Ident(ident) if after_at && self.local_labels.contains(&ident.to_string()) => {
+                    let renamed = self.rename(&ident.to_string());
+                    output.push(TokenTree::Ident(Ident::new(&renamed, self.span)));
+                },
+                TokenTree::Ident(ident) if self.params.contains_key(&ident.to_string()) => {
+                    output.extend(self.params[&ident.to_string()].iter().cloned());
+                },
+                TokenTree::Group(group) => {
+                    let stream = self.apply(&group.stream().into_iter().collect::<Vec<_>>());
+                    let mut group = Group::new(group.delimiter(), stream.into_iter().collect());
+                    group.set_span(self.span);
+                    output.push(TokenTree::Group(group));
+                },
+                other => {
+                    let mut other = other.clone();
+                    other.set_span(self.span);
+                    output.push(other);
+                },
+            }
+
+            // `@.name` is renamed just like `@name`.
+            after_at = match token {
+                TokenTree::Punct(punct) if punct.as_char() == '@' => true,
+                TokenTree::Punct(punct) if punct.as_char() == '.' => after_at,
+                _ => false,
+            };
+        }
+
+        output
+    }
+}
+
+
+/// Splits tokens after every top level `;`. Anything
+/// after the last `;` becomes a statement of its own.
+fn split_statements(tokens: Vec<TokenTree>) -> Vec<Vec<TokenTree>> {
+    let mut statements = vec![];
+    let mut statement = vec![];
+    for token in tokens {
+        let end = matches!(&token, TokenTree::Punct(punct) if punct.as_char() == ';');
+        statement.push(token);
+        if end {
+            statements.push(std::mem::take(&mut statement));
+        }
+    }
+    if !statement.is_empty() {
+        statements.push(statement);
+    }
+    statements
+}
+
+/// Splits a call's arguments at every top level `,`,
+/// leaving off the closing `;`.
+fn split_arguments(tokens: &[TokenTree]) -> Vec<Vec<TokenTree>> {
+    let tokens = match tokens.last() {
+        Some(TokenTree::Punct(punct)) if punct.as_char() == ';' => &tokens[..tokens.len() - 1],
+        _ => tokens,
+    };
+
+    tokens
+        .split(|token| matches!(token, TokenTree::Punct(punct) if punct.as_char() == ','))
+        .map(|arg| arg.to_vec())
+        .collect()
+}
+
+/// How many tokens the `@label`, `@.label` and `@@`
+/// definitions at the start of `statement` take up.
+fn leading_labels(statement: &[TokenTree]) -> usize {
+    let mut count = 0;
+    loop {
+        count += match &statement[count..] {
+            [TokenTree::Punct(at), TokenTree::Ident(_), ..] if at.as_char() == '@' => 2,
+            [TokenTree::Punct(at), TokenTree::Punct(dot), TokenTree::Ident(_), ..]
+                if at.as_char() == '@' && dot.as_char() == '.' => 3,
+            [TokenTree::Punct(at), TokenTree::Punct(second), ..]
+                if at.as_char() == '@' && second.as_char() == '@' => 2,
+            _ => return count,
+        };
+    }
+}
+
+/// The span of the `.` if `statement` is the directive `name`.
+fn directive(statement: &[TokenTree], name: &str) -> Option<Span> {
+    match statement {
+        [TokenTree::Punct(dot), TokenTree::Ident(ident), ..]
+            if dot.as_char() == '.' && ident == name => Some(dot.span()),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubling_macros_hit_the_expansion_limit() {
+        let err = crate::assemble(".macro a; a; a; .endm; a; EXIT;").unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("more than 10000 times"), "{}", message);
+        assert_eq!(message.matches("more than 10000 times").count(), 1, "{}", message);
+    }
+
+    #[test]
+    fn many_calls_under_the_limit_expand() {
+        let source = format!(".macro n; NOP; .endm; {} EXIT;", "n; ".repeat(MACRO_EXPANSION_LIMIT));
+        let program = crate::assemble(&source).unwrap();
+        assert_eq!(program.instructions.len(), MACRO_EXPANSION_LIMIT + 1);
+    }
+}
@@ -0,0 +1,226 @@
+mod data;
+mod expr;
+mod label;
+mod macros;
+mod types;
+mod util;
+
+pub use data::*;
+pub use expr::*;
+pub use label::*;
+pub use macros::*;
+pub use types::*;
+use roc_cpu_types::*;
+use syn::{Error, Ident, Result};
+
+
+fn label_to_address(label: &RocAsmLabel, symbols: &RocAsmSymbols) -> Result<(u8, u8)> {
+    if let Some(loc) = symbols.labels.get(&label.name) {
+        let loc = *loc as u16;
+        let lo = loc as u8;
+        let hi = (loc >> 8) as u8;
+        Ok((hi, lo))
+    } else if symbols.data_labels.contains_key(&label.name) {
+        Err(Error::new(
+            label.span,
+            format!( "Label \"{}\" marks data, and can't be jumped to.", label )
+        ))
+    } else {
+        Err(label.undefined())
+    }
+}
+
+/// A jump or call target, written as a code label
+/// or as an expression, split into `hi, lo`.
+fn jump_target(arg: &RocCPULiteral, op_name: &Ident, symbols: &RocAsmSymbols) -> Result<(u8, u8)> {
+    match arg {
+        RocCPULiteral::Label(label) => label_to_address(label, symbols),
+        RocCPULiteral::Number(expr) => {
+            // Label arithmetic, like `@loop + 1`
+            let target = expr.evaluate_jump_target(symbols)?;
+            Ok(((target >> 8) as u8, target as u8))
+        },
+        _ => Err(Error::new(
+            arg.span(),
+            format!( "{} expects a label here.", op_name )
+        )),
+    }
+}
+
+/// A memory address, written as a data label
+/// or as an expression, split into `hi, lo`.
+fn address(arg: &RocCPULiteral, op_name: &Ident, symbols: &RocAsmSymbols) -> Result<(u8, u8)> {
+    let address = match arg {
+        RocCPULiteral::Label(label) => match symbols.data_labels.get(&label.name) {
+            Some(address) => *address,
+            None => {
+                // Reuse the errors for jump labels.
+                label_to_address(label, symbols)?;
+                return Err(Error::new(
+                    label.span,
+                    format!( "Label \"{}\" marks an instruction, {} expects a data address here.", label, op_name )
+                ));
+            }
+        },
+        RocCPULiteral::Number(expr) => expr.evaluate_address(symbols)? as u16,
+        _ => return Err(Error::new(
+            arg.span(),
+            format!( "{} expects an address here.", op_name )
+        )),
+    };
+    Ok(((address >> 8) as u8, address as u8))
+}
+
+fn register(arg: &RocCPULiteral, op_name: &Ident) -> Result<RocCPURegister> {
+    match arg {
+        RocCPULiteral::Register(reg, _) => Ok(*reg),
+        _ => Err(Error::new(
+            arg.span(),
+            format!( "{} expects a register here.", op_name )
+        )),
+    }
+}
+
+fn number(arg: &RocCPULiteral, op_name: &Ident, symbols: &RocAsmSymbols) -> Result<u8> {
+    match arg {
+        RocCPULiteral::Number(expr) => expr.evaluate_byte(symbols),
+        _ => Err(Error::new(
+            arg.span(),
+            format!( "{} expects a number here.", op_name )
+        )),
+    }
+}
+
+fn invalid_opcode(op_name: &Ident) -> Error {
+    Error::new(op_name.span(), format!("{} is not a valid opcode.", op_name))
+}
+
+fn wrong_argument_count(op_name: &Ident, forms: &[&RocCPUInstructionForm]) -> Error {
+    let mut counts: Vec<_> = forms.iter()
+        .flat_map(|form| {
+            let combined = form.address.map(|_| form.operands.len() - 1);
+            std::iter::once(form.operands.len()).chain(combined)
+        })
+        .collect();
+    counts.sort();
+    counts.dedup();
+
+    let plural = if counts == [1] { "" } else { "s" };
+    let counts: Vec<_> = counts.iter().map(|count| count.to_string()).collect();
+    Error::new(
+        op_name.span(),
+        format!("{} takes {} argument{}.", op_name, counts.join(" or "), plural)
+    )
+}
+
+
+/// Translates an operation into the instruction it stands
+/// for. Each mnemonic's forms come from the `#[mnemonic]`
+/// attributes on `RocCPUInstruction`, and are tried in
+/// order until one accepts the arguments.
+pub fn translate_asm_to_instruction(operation: Operation, symbols: &RocAsmSymbols) -> Result<Option<RocCPUInstruction>> {
+    let (op_name, args) = match operation {
+        Operation::LabelOperation { .. }
+        | Operation::ConstDirective { .. }
+        | Operation::AliasDirective { .. }
+        | Operation::DataDirective { .. } => {
+            // We just ignore labels, since we don't
+            // translate them to anything in particular.
+            // They get compiled down to line indexes
+            // at compiletime. Directives are the same.
+            return Ok(None);
+        },
+
+        Operation::OperationNoArgs { op_name } => (op_name, vec![]),
+        Operation::OperationOneArg { op_name, value_arg1 } => (op_name, vec![value_arg1]),
+        Operation::OperationTwoArg { op_name, value_arg1, value_arg2 } => {
+            (op_name, vec![value_arg1, value_arg2])
+        },
+        Operation::OperationThreeArg { op_name, value_arg1, value_arg2, value_arg3 } => {
+            (op_name, vec![value_arg1, value_arg2, value_arg3])
+        },
+    };
+
+    let forms: Vec<_> = RocCPUInstruction::FORMS.iter()
+        .filter(|form| op_name == form.mnemonic)
+        .collect();
+    if forms.is_empty() {
+        return Err(invalid_opcode(&op_name));
+    }
+
+    // If none of them fit, the error comes from the form
+    // that got furthest through the arguments, or the first
+    // of those, since it's most likely the one meant.
+    let mut error: Option<(usize, Error)> = None;
+    for form in &forms {
+        match translate_form(form, &op_name, &args, symbols) {
+            Ok(Some(instruction)) => return Ok(Some(instruction)),
+            Ok(None) => {},
+            Err((index, err)) => {
+                if error.as_ref().is_none_or(|(furthest, _)| index > *furthest) {
+                    error = Some((index, err));
+                }
+            },
+        }
+    }
+
+    Err(error.map_or_else(|| wrong_argument_count(&op_name, &forms), |(_, err)| err))
+}
+
+/// Reads `args` as `form`, or returns `None` if there
+/// are the wrong number of them. Errors come with the
+/// index of the argument that didn't fit.
+fn translate_form(
+    form: &RocCPUInstructionForm,
+    op_name: &Ident,
+    args: &[RocCPULiteral],
+    symbols: &RocAsmSymbols
+) -> std::result::Result<Option<RocCPUInstruction>, (usize, Error)> {
+    // An address can be written as one argument
+    // instead of its `hi, lo` bytes.
+    let address_index = form.address_index()
+        .filter(|_| args.len() + 1 == form.operands.len());
+    if address_index.is_none() && args.len() != form.operands.len() {
+        return Ok(None);
+    }
+
+    let mut operands = Vec::with_capacity(form.operands.len());
+    let mut args = args.iter().enumerate();
+    while operands.len() < form.operands.len() {
+        let (index, arg) = args.next().unwrap();
+        let at = |err| (index, err);
+
+        if address_index == Some(operands.len()) {
+            let (hi, lo) = match form.address {
+                Some(RocCPUAddressKind::Code) => jump_target(arg, op_name, symbols).map_err(at)?,
+                _ => address(arg, op_name, symbols).map_err(at)?,
+            };
+            operands.push(RocCPUOperand::Byte(hi));
+            operands.push(RocCPUOperand::Byte(lo));
+            continue;
+        }
+
+        operands.push(match form.operands[operands.len()] {
+            RocCPUOperandKind::Register => RocCPUOperand::Register(register(arg, op_name).map_err(at)?),
+            RocCPUOperandKind::Byte => RocCPUOperand::Byte(number(arg, op_name, symbols).map_err(at)?),
+        });
+    }
+
+    Ok(RocCPUInstruction::from_operands(form.variant, &operands))
+}
+
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn errors_come_from_the_form_that_fits_furthest() {
+        let err = crate::assemble("LOAD $ax, $bx, 5; EXIT;").unwrap_err();
+        assert_eq!(err.to_string(), "1:16: LOAD expects a register here.");
+    }
+
+    #[test]
+    fn errors_come_from_the_first_form_when_tied() {
+        let err = crate::assemble("LOAD 5, 0, 0; EXIT;").unwrap_err();
+        assert_eq!(err.to_string(), "1:6: LOAD expects a register here.");
+    }
+}
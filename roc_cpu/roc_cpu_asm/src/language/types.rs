@@ -0,0 +1,534 @@
+use syn::*;
+use std::collections::{HashMap, HashSet};
+use roc_cpu_types::RocCPURegister;
+use super::{RocAsmData, RocAsmDataEntry, RocAsmExpr, RocAsmLabel, RocAsmLabelKind};
+use crate::RocAsmWarning;
+
+// OVERALL TYPE //
+
+#[derive(Clone)]
+pub struct Program {
+    pub operations: Vec<Operation>,
+    pub data: Vec<RocAsmDataEntry>,
+    pub symbols: RocAsmSymbols,
+
+    /// Labels waiting for the next instruction or data
+    /// directive, which decides which kind they are.
+    pending_labels: Vec<RocAsmLabel>,
+
+    /// The last global label, which `@.local` labels
+    /// are scoped to.
+    scope: String,
+
+    /// How many `@@` labels have been defined so far.
+    anonymous_labels: usize,
+
+    /// Every label defined, in the order they were
+    /// first defined.
+    definitions: Vec<RocAsmLabel>,
+
+    /// Label name to its index in `definitions`.
+    defined: HashMap<String, usize>,
+
+    /// Every label name an operand has used.
+    references: HashSet<String>,
+
+    /// Whether the last instruction was a `JUMP`, `RETURN`
+    /// or `EXIT` with no label after it, so the next
+    /// instruction can never run.
+    unreachable: bool,
+
+    pub warnings: Vec<RocAsmWarning>,
+}
+
+/// Every name a program defines, for resolving the
+/// operands of its instructions.
+#[derive(Clone, Default)]
+pub struct RocAsmSymbols {
+    /// Label name to instruction index.
+    pub labels: HashMap<String, usize>,
+    /// Data label name to its address in memory.
+    pub data_labels: HashMap<String, u16>,
+    /// `.const` name to the expression it stands for.
+    pub constants: HashMap<String, RocAsmExpr>,
+    /// `.alias` name to the register it stands for.
+    pub aliases: HashMap<String, RocCPURegister>,
+}
+
+impl Program {
+    /// Parses as much of the program as it can, handing back
+    /// the errors from any statements it had to skip.
+    pub fn parse_recovering(input: parse::ParseStream) -> Result<(Self, Option<Error>)> {
+        let mut prgm = Self {
+            operations: vec![],
+            data: vec![],
+            symbols: RocAsmSymbols::default(),
+            pending_labels: vec![],
+            scope: String::new(),
+            anonymous_labels: 0,
+            definitions: vec![],
+            defined: HashMap::new(),
+            references: HashSet::new(),
+            unreachable: false,
+            warnings: vec![],
+        };
+        let mut errors: Option<Error> = None;
+        
+        while !input.is_empty() {
+            let op: Operation = match input.parse() {
+                Ok(op) => op,
+                Err(err) => {
+                    // Keep going from the next statement, so
+                    // that every mistake gets reported at once.
+                    push_error(&mut errors, err);
+                    skip_statement(input)?;
+                    continue;
+                }
+            };
+
+            if let Err(err) = prgm.add_operation(op) {
+                push_error(&mut errors, err);
+            }
+        }
+
+        // Labels at the very end mark the end of the code.
+        prgm.attach_labels_to_code();
+
+        Ok((prgm, errors))
+    }
+
+    /// Warns about every label that's defined but never
+    /// used, apart from `macro_labels`, the names macro
+    /// expansion gave the labels in its bodies, since
+    /// they're only used if the body is.
+    pub fn warn_unused_labels(&mut self, macro_labels: &HashSet<String>) {
+        let from_macro = |label: &RocAsmLabel| match &label.kind {
+            RocAsmLabelKind::Global(name) | RocAsmLabelKind::Local(name) => macro_labels.contains(name),
+            _ => false,
+        };
+        let unused = self.definitions.iter()
+            .filter(|label| !self.references.contains(&label.name) && !from_macro(label));
+
+        for label in unused {
+            self.warnings.push(RocAsmWarning::new(
+                label.span,
+                format!("Label \"{}\" is never used.", label)
+            ));
+        }
+    }
+
+    /// Remembers where `label` was defined, or fails if
+    /// it has been defined before.
+    fn define_label(&mut self, label: &RocAsmLabel) -> Result<()> {
+        // Spans are all that's reported, since under `roc_asm!`
+        // they don't know their line numbers.
+        if let Some(first) = self.defined.get(&label.name) {
+            let mut err = Error::new(label.span, format!("Label \"{}\" is defined twice.", label));
+            err.combine(Error::new(
+                self.definitions[*first].span,
+                format!("Label \"{}\" is first defined here.", label)
+            ));
+            return Err(err);
+        }
+        self.defined.insert(label.name.clone(), self.definitions.len());
+        self.definitions.push(label.clone());
+        Ok(())
+    }
+
+    fn attach_labels_to_code(&mut self) {
+        for label in self.pending_labels.drain(..) {
+            self.symbols.labels.insert(label.name, self.operations.len());
+        }
+    }
+
+    fn add_operation(&mut self, mut op: Operation) -> Result<()> {
+        for label in op.labels_mut() {
+            label.resolve(&self.scope, self.anonymous_labels);
+        }
+        if !matches!(op, Operation::LabelOperation { .. }) {
+            for label in op.labels_mut() {
+                self.references.insert(label.name.clone());
+            }
+        }
+
+        match op {
+            Operation::LabelOperation { label } => {
+                match &label.kind {
+                    RocAsmLabelKind::Global(name) => self.scope = name.clone(),
+                    RocAsmLabelKind::Anonymous => self.anonymous_labels += 1,
+                    RocAsmLabelKind::Local(_) => {},
+                    _ => return Err(Error::new(
+                        label.span,
+                        format!("\"{}\" can only be used, not defined. Write @@ for an anonymous label.", label)
+                    )),
+                }
+                self.define_label(&label)?;
+                self.unreachable = false;
+                self.pending_labels.push(label);
+            },
+            Operation::DataDirective { directive, data } => {
+                let labels = std::mem::take(&mut self.pending_labels);
+                self.data.push(RocAsmDataEntry { labels, data, span: directive.span() });
+            },
+            Operation::ConstDirective { name, value } => {
+                if self.symbols.constants.contains_key(&name.to_string()) {
+                    return Err(Error::new(
+                        name.span(),
+                        format!("Constant \"{}\" is already defined.", name)
+                    ));
+                }
+                self.symbols.constants.insert(name.to_string(), value);
+            },
+            Operation::AliasDirective { name, mut register } => {
+                if crate::language::util::register_from_ident(&name).is_ok() {
+                    return Err(Error::new(
+                        name.span(),
+                        format!("\"${}\" is already a register, and can't be an alias.", name)
+                    ));
+                }
+                self.resolve_alias(&mut register)?;
+                match register {
+                    RocCPULiteral::Register(register, _) => {
+                        self.symbols.aliases.insert(name.to_string(), register);
+                    },
+                    _ => return Err(Error::new(
+                        register.span(),
+                        ".alias expects a register here."
+                    )),
+                }
+            },
+            _ => {
+                // Aliases are swapped for their registers
+                // here, so they must be defined before use.
+                for arg in op.arguments_mut() {
+                    self.resolve_alias(arg)?;
+                }
+                self.attach_labels_to_code();
+
+                let op_name = op.op_name().map(|name| name.to_string());
+                if self.unreachable {
+                    if let Some(op_name) = op.op_name() {
+                        self.warnings.push(RocAsmWarning::new(
+                            op_name.span(),
+                            "This instruction can never run, there is no label before it.".to_string()
+                        ));
+                    }
+                    // Only warn once for each unreachable block.
+                    self.unreachable = false;
+                } else {
+                    self.unreachable = matches!(op_name.as_deref(), Some("JUMP" | "RETURN" | "EXIT"));
+                }
+
+                self.operations.push(op);
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_alias(&self, arg: &mut RocCPULiteral) -> Result<()> {
+        if let RocCPULiteral::Alias(name) = arg {
+            let register = match self.symbols.aliases.get(&name.to_string()) {
+                Some(register) => *register,
+                None => crate::language::util::register_from_ident(name)?,
+            };
+            *arg = RocCPULiteral::Register(register, name.span());
+        }
+        Ok(())
+    }
+}
+
+impl syn::parse::Parse for Program {
+    fn parse(input: parse::ParseStream) -> Result<Self> {
+        match Self::parse_recovering(input)? {
+            (_, Some(err)) => Err(err),
+            (prgm, None) => Ok(prgm),
+        }
+    }
+}
+
+/// Adds `err` to the errors collected so far.
+pub fn push_error(errors: &mut Option<Error>, err: Error) {
+    match errors {
+        Some(errors) => errors.combine(err),
+        None => *errors = Some(err),
+    }
+}
+
+/// Skips to just after the next `;`.
+fn skip_statement(input: parse::ParseStream) -> Result<()> {
+    input.step(|cursor| {
+        let mut rest = *cursor;
+        while let Some((tt, next)) = rest.token_tree() {
+            rest = next;
+            if matches!(tt, proc_macro2::TokenTree::Punct(ref punct) if punct.as_char() == ';') {
+                break;
+            }
+        }
+        Ok(((), rest))
+    })
+}
+
+
+// OPERATION TYPE //
+
+
+#[derive(Clone)]
+pub enum Operation {
+    LabelOperation { label: RocAsmLabel },
+    ConstDirective { name: Ident, value: RocAsmExpr },
+    AliasDirective { name: Ident, register: RocCPULiteral },
+    DataDirective { directive: Ident, data: RocAsmData },
+    OperationNoArgs { op_name: Ident },
+    OperationOneArg { op_name: Ident, value_arg1: RocCPULiteral },
+    OperationTwoArg { op_name: Ident, value_arg1: RocCPULiteral, value_arg2: RocCPULiteral },
+    OperationThreeArg { op_name: Ident, value_arg1: RocCPULiteral, value_arg2: RocCPULiteral, value_arg3: RocCPULiteral },
+}
+
+
+impl Operation {
+    /// The arguments written after the opcode.
+    pub fn arguments_mut(&mut self) -> Vec<&mut RocCPULiteral> {
+        match self {
+            Self::OperationOneArg { value_arg1, .. } => vec![value_arg1],
+            Self::OperationTwoArg { value_arg1, value_arg2, .. } => vec![value_arg1, value_arg2],
+            Self::OperationThreeArg { value_arg1, value_arg2, value_arg3, .. } => {
+                vec![value_arg1, value_arg2, value_arg3]
+            },
+            _ => vec![],
+        }
+    }
+
+    /// The opcode, for operations that are instructions.
+    pub fn op_name(&self) -> Option<&Ident> {
+        match self {
+            Self::OperationNoArgs { op_name }
+            | Self::OperationOneArg { op_name, .. }
+            | Self::OperationTwoArg { op_name, .. }
+            | Self::OperationThreeArg { op_name, .. } => Some(op_name),
+            _ => None,
+        }
+    }
+
+    /// Every label this operation defines or uses, including
+    /// those inside expressions.
+    pub fn labels_mut(&mut self) -> Vec<&mut RocAsmLabel> {
+        let mut labels = vec![];
+        match self {
+            Self::LabelOperation { label } => labels.push(label),
+            Self::ConstDirective { value, .. } => value.labels_mut(&mut labels),
+            Self::DataDirective { data, .. } => data.labels_mut(&mut labels),
+            Self::AliasDirective { .. } | Self::OperationNoArgs { .. } => {},
+            Self::OperationOneArg { value_arg1, .. } => value_arg1.labels_mut(&mut labels),
+            Self::OperationTwoArg { value_arg1, value_arg2, .. } => {
+                value_arg1.labels_mut(&mut labels);
+                value_arg2.labels_mut(&mut labels);
+            },
+            Self::OperationThreeArg { value_arg1, value_arg2, value_arg3, .. } => {
+                value_arg1.labels_mut(&mut labels);
+                value_arg2.labels_mut(&mut labels);
+                value_arg3.labels_mut(&mut labels);
+            },
+        }
+        labels
+    }
+
+    fn parse_directive(input: parse::ParseStream) -> Result<Self> {
+        use syn::ext::IdentExt;
+
+        input.parse::<Token![.]>()?;
+        let directive = Ident::parse_any(input)?;
+
+        let op = match directive.to_string().as_str() {
+            "const" => {
+                // .const NAME = expr;
+                let name: Ident = input.parse()?;
+                input.parse::<Token![=]>()?;
+                let value: RocAsmExpr = input.parse()?;
+                Self::ConstDirective { name, value }
+            },
+            "alias" => {
+                // .alias name $reg;
+                let name: Ident = input.parse()?;
+                let register: RocCPULiteral = input.parse()?;
+                Self::AliasDirective { name, register }
+            },
+            _ => match RocAsmData::parse_directive(&directive, input)? {
+                Some(data) => Self::DataDirective { directive, data },
+                None => {
+                    return Err(Error::new(
+                        directive.span(),
+                        format!(".{} is not a valid directive.", directive)
+                    ));
+                }
+            }
+        };
+
+        input.parse::<Token![;]>()?;
+        Ok(op)
+    }
+}
+
+
+impl syn::parse::Parse for Operation {
+    fn parse(input: parse::ParseStream) -> Result<Self> {
+
+        let lookahead = input.lookahead1();
+        if lookahead.peek(Token![@]) {
+            let label: RocAsmLabel = input.parse()?;
+            return Ok(Self::LabelOperation { label });
+        }
+
+        if lookahead.peek(Token![.]) {
+            return Self::parse_directive(input);
+        }
+
+        let op_name: Ident = input.parse()?;
+
+        let lookahead = input.lookahead1();
+        if lookahead.peek(Token![;]) {
+            input.parse::<Token![;]>()?;
+            return Ok(Self::OperationNoArgs { op_name });
+        }
+        
+        // At least one argument
+        let value_arg1: RocCPULiteral = input.parse()?;
+        let lookahead = input.lookahead1();
+        if lookahead.peek(Token![;]) {
+            input.parse::<Token![;]>()?;
+            return Ok(Self::OperationOneArg { op_name, value_arg1 });
+        }
+
+        // At least two arguments
+        input.parse::<Token![,]>()?;
+        let value_arg2: RocCPULiteral = input.parse()?;
+        let lookahead = input.lookahead1();
+        if lookahead.peek(Token![;]) {
+            input.parse::<Token![;]>()?;
+            return Ok(Self::OperationTwoArg { op_name, value_arg1, value_arg2 });
+        }
+
+        // Three arguments
+        input.parse::<Token![,]>()?;
+        let value_arg3: RocCPULiteral = input.parse()?;
+        input.parse::<Token![;]>()?;
+
+        Ok( Self::OperationThreeArg { op_name, value_arg1, value_arg2, value_arg3 } )
+    }
+}
+
+// LITERAL TYPE (DATA AND REGISTERS)
+
+#[derive(Clone)]
+pub enum RocCPULiteral {
+    Number(RocAsmExpr),
+    Register(RocCPURegister, proc_macro2::Span),
+    Label(RocAsmLabel),
+
+    /// A `$name` that isn't a register, until
+    /// it's looked up in the `.alias` table.
+    Alias(Ident),
+}
+
+impl RocCPULiteral {
+    /// Every label used in this literal.
+    pub fn labels_mut<'a>(&'a mut self, labels: &mut Vec<&'a mut RocAsmLabel>) {
+        match self {
+            Self::Number(expr) => expr.labels_mut(labels),
+            Self::Label(label) => labels.push(label),
+            Self::Register(..) | Self::Alias(_) => {},
+        }
+    }
+
+    /// Where this literal was written, for errors.
+    pub fn span(&self) -> proc_macro2::Span {
+        match self {
+            Self::Number(expr) => expr.span(),
+            Self::Register(_, span) => *span,
+            Self::Label(label) => label.span,
+            Self::Alias(name) => name.span(),
+        }
+    }
+}
+
+impl syn::parse::Parse for RocCPULiteral {
+
+    fn parse(input: parse::ParseStream) -> Result<Self> {
+        
+        use crate::language::util::register_from_ident;
+
+        if input.peek(Token![$]) {
+            // This is a register
+            input.parse::<Token![$]>()?;
+            let reg_ident: Ident = input.parse()?;
+            
+            return Ok(match register_from_ident(&reg_ident) {
+                Ok(register) => RocCPULiteral::Register(register, reg_ident.span()),
+                Err(_) => RocCPULiteral::Alias(reg_ident),
+            });
+        }
+
+        if input.peek(Token![@]) {
+            // This is a label, unless it's part of
+            // a longer expression like `@table + 3`
+            let fork = input.fork();
+            fork.parse::<RocAsmLabel>()?;
+
+            if fork.is_empty() || fork.peek(Token![,]) || fork.peek(Token![;]) {
+                return Ok(RocCPULiteral::Label(input.parse()?));
+            }
+        }
+
+        if !RocAsmExpr::peek(input) {
+            return Err(input.error("Expected a register, a label or a number here."));
+        }
+
+        Ok(Self::Number(input.parse()?))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use quote::quote;
+
+    use crate::RocAsmError;
+
+    #[test]
+    fn duplicate_labels_point_at_both_definitions() {
+        let Err(RocAsmError::Assembly(diagnostics)) = crate::assemble("@a NOP;\n@a EXIT;") else {
+            panic!("expected the duplicate label to be an error");
+        };
+        let found: Vec<_> = diagnostics.iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(found, [
+            (2, "Label \"@a\" is defined twice."),
+            (1, "Label \"@a\" is first defined here."),
+        ]);
+    }
+
+    #[test]
+    fn unused_labels_are_warned_about_in_order() {
+        // Tokens made here all have the same span, as they
+        // do under `roc_asm!`.
+        let tokens = quote! { @c NOP; @a NOP; @d NOP; @b EXIT; };
+        let (_, warnings) = crate::assemble_tokens(tokens, Path::new("")).unwrap();
+        let messages: Vec<_> = warnings.iter().map(|warning| warning.message.as_str()).collect();
+        assert_eq!(messages, [
+            "Label \"@c\" is never used.",
+            "Label \"@a\" is never used.",
+            "Label \"@d\" is never used.",
+            "Label \"@b\" is never used.",
+        ]);
+    }
+
+    #[test]
+    fn only_labels_from_macros_are_exempt_from_unused_warnings() {
+        let source = ".macro mark; @here NOP; .endm;\n@__mine NOP;\nmark;\nEXIT;";
+        let (_, warnings) = crate::assemble_with_warnings(source).unwrap();
+        let messages: Vec<_> = warnings.iter().map(|warning| warning.message.as_str()).collect();
+        assert_eq!(messages, ["Label \"@__mine\" is never used."]);
+    }
+}
@@ -0,0 +1,40 @@
+use syn::{Error, Ident, Result};
+use roc_cpu_types::RocCPURegister;
+
+pub fn register_from_ident(register: &Ident) -> Result<RocCPURegister> {
+
+    let register_name = register.to_string();
+    let register = match register_name.as_str() {
+
+        // RETURN REGISTER
+
+        "ret" => RocCPURegister::ReturnValue,
+
+        // GENERAL PURPOSE REGISTERS
+
+        "ax" => RocCPURegister::GeneralPurposeA,
+        "bx" => RocCPURegister::GeneralPurposeB,
+        "cx" => RocCPURegister::GeneralPurposeC,
+        "dx" => RocCPURegister::GeneralPurposeD,
+
+        // FUNCTION ARGUMENT REGISTERS
+
+        "f1" => RocCPURegister::FunctionParameter1,
+        "f2" => RocCPURegister::FunctionParameter2,
+        "f3" => RocCPURegister::FunctionParameter3,
+        "f4" => RocCPURegister::FunctionParameter4,
+
+        "fret" => RocCPURegister::FunctionReturn,
+
+
+        _ => {
+            return Err(Error::new(
+                register.span(),
+                format!("Register \"${}\" is not a valid register.", register_name)
+            ));
+        }
+
+    };
+
+    Ok(register)
+}
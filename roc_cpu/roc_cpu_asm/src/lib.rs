@@ -0,0 +1,243 @@
+//! The Roc assembly language, shared by the `roc_asm!`
+//! macro and by the runtime assembler.
+//!
+//! Source text is read with the Rust tokenizer, so
+//! `.rasm` files follow exactly the same syntax as the
+//! body of a `roc_asm!` invocation, comments included.
+
+mod disassemble;
+mod error;
+mod language;
+mod listing;
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use proc_macro2::TokenStream;
+use syn::parse::Parser;
+use roc_cpu_types::*;
+
+pub use disassemble::{disassemble, disassemble_image, disassemble_with_labels};
+pub use error::*;
+pub use listing::{RocAsmListing, RocAsmListingLine, RocAsmListingLocation};
+pub use language::{
+    DATA_START,
+    MACRO_DEPTH_LIMIT,
+    MACRO_EXPANSION_LIMIT,
+    Operation,
+    Program,
+    RocAsmData,
+    RocAsmDataLayout,
+    RocAsmDataEntry,
+    RocAsmExpr,
+    RocAsmSymbols,
+    RocCPULiteral,
+    translate_asm_to_instruction,
+};
+
+/// Assembles a stream of tokens, such as the body of
+/// a `roc_asm!` invocation, along with any warnings.
+/// `.incbin` paths are relative to `base_dir`.
+pub fn assemble_tokens(tokens: TokenStream, base_dir: &Path) -> syn::Result<(RocCPUProgram, Vec<RocAsmWarning>)> {
+    let assembled = assemble_parts(tokens, base_dir)?;
+    Ok((assembled.program, assembled.warnings))
+}
+
+/// Like `assemble_tokens`, but also hands back the path
+/// of every file `.incbin` read, so that whatever built
+/// the program can be rebuilt when they change.
+pub fn assemble_tokens_with_includes(
+    tokens: TokenStream,
+    base_dir: &Path
+) -> syn::Result<(RocCPUProgram, Vec<RocAsmWarning>, Vec<PathBuf>)> {
+    let assembled = assemble_parts(tokens, base_dir)?;
+    Ok((assembled.program, assembled.warnings, assembled.included_files))
+}
+
+/// Everything the assembler worked out, for the
+/// functions that want more than the program.
+pub(crate) struct Assembled {
+    pub program: RocCPUProgram,
+    pub warnings: Vec<RocAsmWarning>,
+    pub symbols: RocAsmSymbols,
+
+    /// Where each instruction was written.
+    pub instruction_spans: Vec<proc_macro2::Span>,
+
+    /// The address, length and span of each data directive.
+    pub data_layout: Vec<(u16, usize, proc_macro2::Span)>,
+
+    /// Every data directive, in the same order
+    /// as `data_layout`.
+    pub data_entries: Vec<RocAsmDataEntry>,
+
+    /// Every file read by `.incbin`, in the order
+    /// they appear.
+    pub included_files: Vec<PathBuf>,
+}
+
+pub(crate) fn assemble_parts(tokens: TokenStream, base_dir: &Path) -> syn::Result<Assembled> {
+    let (tokens, macro_labels, mut errors) = language::expand_macros(tokens);
+
+    let (mut program, parse_errors) = Program::parse_recovering.parse2(tokens)?;
+    if let Some(err) = parse_errors {
+        language::push_error(&mut errors, err);
+    }
+    program.warn_unused_labels(&macro_labels);
+
+    let mut data = vec![];
+    let mut data_layout = vec![];
+    match language::lay_out_data(&program.data, &mut program.symbols, base_dir) {
+        Ok(Some((segment, layout))) => {
+            data.push(segment);
+            data_layout = layout.into_iter()
+                .zip(&program.data)
+                .map(|((address, length), entry)| (address, length, entry.span))
+                .collect();
+        },
+        Ok(None) => {},
+        Err(err) => language::push_error(&mut errors, err),
+    }
+
+    let included_files = program.data.iter()
+        .filter_map(|entry| match &entry.data {
+            RocAsmData::IncBin(path) => Some(base_dir.join(path.value())),
+            _ => None,
+        })
+        .collect();
+
+    let warnings = std::mem::take(&mut program.warnings);
+
+    let mut instructions = vec![];
+    let mut instruction_spans = vec![];
+    for operation in program.operations {
+        let span = operation.op_name().map_or_else(proc_macro2::Span::call_site, |name| name.span());
+        match translate_asm_to_instruction(operation, &program.symbols) {
+            Ok(Some(instruction)) => {
+                instructions.push(instruction);
+                instruction_spans.push(span);
+            },
+            Ok(None) => {},
+            Err(err) => language::push_error(&mut errors, err),
+        }
+    }
+
+    match errors {
+        Some(err) => Err(err),
+        None => Ok(Assembled {
+            program: RocCPUProgram { instructions, data },
+            warnings,
+            symbols: program.symbols,
+            instruction_spans,
+            data_layout,
+            data_entries: program.data,
+            included_files,
+        }),
+    }
+}
+
+/// Assembles Roc assembly source text. `.incbin` paths
+/// are relative to the current directory.
+pub fn assemble(source: &str) -> Result<RocCPUProgram, RocAsmError> {
+    Ok(assemble_with_warnings(source)?.0)
+}
+
+/// Like `assemble`, but also hands back warnings, such
+/// as labels that are never used.
+pub fn assemble_with_warnings(source: &str) -> Result<(RocCPUProgram, Vec<RocAsmDiagnostic>), RocAsmError> {
+    assemble_in(source, Path::new(""))
+}
+
+/// Assembles Roc assembly source text into the bytes that
+/// should be placed in memory at `load_address`, with
+/// labels resolved to byte addresses.
+///
+/// Any data the program declares follows the code, padded
+/// with zeroes out to `DATA_START`. It's an error for the
+/// code to run past the start of the data.
+pub fn assemble_to_image(source: &str, load_address: u16) -> Result<Vec<u8>, RocAsmError> {
+    let tokens = TokenStream::from_str(source)?;
+    let assembled = assemble_parts(tokens, Path::new(""))?;
+    let mut image = encode_program(&assembled.program, load_address);
+
+    for segment in &assembled.program.data {
+        let offset = (segment.address as usize).checked_sub(load_address as usize)
+            .filter(|offset| *offset >= image.len());
+        let Some(offset) = offset else {
+            let span = assembled.data_layout.first().map_or_else(proc_macro2::Span::call_site, |(_, _, span)| *span);
+            return Err(RocAsmError::Assembly(vec![RocAsmDiagnostic::from_span(
+                span,
+                format!("The program's code overlaps its data when loaded at {:#06x}.", load_address)
+            )]));
+        };
+        image.resize(offset, 0);
+        image.extend(&segment.bytes);
+    }
+
+    Ok(image)
+}
+
+/// Assembles a `.rasm` file. `.incbin` paths are
+/// relative to the directory the file is in.
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<RocCPUProgram, RocAsmError> {
+    Ok(assemble_file_with_warnings(path)?.0)
+}
+
+/// Like `assemble_file`, but also hands back warnings.
+pub fn assemble_file_with_warnings<P: AsRef<Path>>(path: P) -> Result<(RocCPUProgram, Vec<RocAsmDiagnostic>), RocAsmError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)?;
+    assemble_in(&source, path.parent().unwrap_or(Path::new("")))
+}
+
+/// Like `assemble`, but also hands back a listing of the
+/// program. With a `load_address`, the listing shows byte
+/// addresses and relocated jumps, and otherwise shows
+/// instruction indexes.
+pub fn assemble_with_listing(source: &str, load_address: Option<u16>) -> Result<(RocCPUProgram, RocAsmListing), RocAsmError> {
+    listing_in(source, Path::new(""), load_address)
+}
+
+/// Like `assemble_file`, but also hands back a listing.
+pub fn assemble_file_with_listing<P: AsRef<Path>>(path: P, load_address: Option<u16>) -> Result<(RocCPUProgram, RocAsmListing), RocAsmError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)?;
+    listing_in(&source, path.parent().unwrap_or(Path::new("")), load_address)
+}
+
+fn listing_in(source: &str, base_dir: &Path, load_address: Option<u16>) -> Result<(RocCPUProgram, RocAsmListing), RocAsmError> {
+    let tokens = TokenStream::from_str(source)?;
+    let assembled = assemble_parts(tokens, base_dir)?;
+    let listing = RocAsmListing::new(&assembled, source, load_address);
+    Ok((assembled.program, listing))
+}
+
+fn assemble_in(source: &str, base_dir: &Path) -> Result<(RocCPUProgram, Vec<RocAsmDiagnostic>), RocAsmError> {
+    let tokens = TokenStream::from_str(source)?;
+    let (program, warnings) = assemble_tokens(tokens, base_dir)?;
+    Ok((program, warnings.iter().map(RocAsmWarning::to_diagnostic).collect()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_include_data() {
+        let source = "@value .byte 7, 8; LOAD $ax, @value; EXIT;";
+        let code = encode_program(&assemble(source).unwrap(), 0x100);
+        let image = assemble_to_image(source, 0x100).unwrap();
+
+        let data_offset = (DATA_START - 0x100) as usize;
+        assert_eq!(image[..code.len()], code);
+        assert!(image[code.len()..data_offset].iter().all(|byte| *byte == 0));
+        assert_eq!(image[data_offset..], [7, 8]);
+    }
+
+    #[test]
+    fn images_with_code_over_their_data_are_errors() {
+        let err = assemble_to_image(".byte 7; EXIT;", DATA_START).unwrap_err();
+        assert!(err.to_string().contains("overlaps its data"), "{}", err);
+    }
+}
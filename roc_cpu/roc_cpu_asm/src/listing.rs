@@ -0,0 +1,214 @@
+//! Listings, which line up every instruction and piece
+//! of data with its location, its bytes, the labels on
+//! it and the source it came from.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use roc_cpu_traits::ProgramEncodable;
+use roc_cpu_types::*;
+
+use crate::Assembled;
+
+/// How many bytes of data go on each line.
+const BYTES_PER_LINE: usize = 8;
+
+
+/// Where a line of the listing sits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RocAsmListingLocation {
+    /// An instruction index, when no load address was given.
+    Index(usize),
+    /// A byte address in memory.
+    Address(u16),
+}
+
+impl fmt::Display for RocAsmListingLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "{:>6}", index),
+            Self::Address(address) => write!(f, "{:#06x}", address),
+        }
+    }
+}
+
+/// One line of a listing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RocAsmListingLine {
+    pub location: RocAsmListingLocation,
+    pub bytes: Vec<u8>,
+
+    /// The labels that point here, as they were resolved,
+    /// so a local label includes the scope it's under.
+    pub labels: Vec<String>,
+
+    /// The source line this came from, counting from 1,
+    /// or 0 for lines that continue the one before.
+    pub line: usize,
+    pub source: String,
+}
+
+/// The output of `assemble_with_listing`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RocAsmListing {
+    pub lines: Vec<RocAsmListingLine>,
+}
+
+impl RocAsmListing {
+    /// Builds the listing for an assembled program. With a
+    /// `load_address`, code is shown at byte addresses with
+    /// its jumps relocated, just as `encode_program` would
+    /// place it.
+    pub(crate) fn new(assembled: &Assembled, source: &str, load_address: Option<u16>) -> Self {
+        let source_lines: Vec<_> = source.lines().collect();
+        let source_text = |span: proc_macro2::Span| {
+            let line = span.start().line;
+            let text = source_lines.get(line.wrapping_sub(1)).map_or("", |text| text.trim());
+            (line, text.to_string())
+        };
+
+        let mut code_labels: HashMap<usize, Vec<String>> = HashMap::new();
+        for (name, index) in &assembled.symbols.labels {
+            code_labels.entry(*index).or_default().push(label_name(name));
+        }
+
+        let instructions = &assembled.program.instructions;
+        let image = load_address.map(|address| encode_program(instructions, address));
+
+        let mut lines = vec![];
+        let mut offset = 0;
+        for (index, (instruction, span)) in instructions.iter().zip(&assembled.instruction_spans).enumerate() {
+            let size = instruction.encode().len();
+            let (location, bytes) = match (load_address, &image) {
+                (Some(address), Some(image)) => (
+                    RocAsmListingLocation::Address(address.wrapping_add(offset as u16)),
+                    image[offset..offset + size].to_vec(),
+                ),
+                _ => (RocAsmListingLocation::Index(index), instruction.encode()),
+            };
+            offset += size;
+
+            let (line, source) = source_text(*span);
+            lines.push(RocAsmListingLine {
+                location,
+                bytes,
+                labels: sorted(code_labels.remove(&index)),
+                line,
+                source,
+            });
+        }
+
+        // A label can point just past the last instruction.
+        if let Some(labels) = code_labels.remove(&instructions.len()) {
+            let location = match load_address {
+                Some(address) => RocAsmListingLocation::Address(address.wrapping_add(offset as u16)),
+                None => RocAsmListingLocation::Index(instructions.len()),
+            };
+            lines.push(RocAsmListingLine {
+                location,
+                bytes: vec![],
+                labels: sorted(Some(labels)),
+                line: 0,
+                source: String::new(),
+            });
+        }
+
+        let data: Vec<u8> = assembled.program.data.iter()
+            .flat_map(|segment| segment.bytes.iter().copied())
+            .collect();
+        let data_start = assembled.program.data.first().map_or(0, |segment| segment.address);
+
+        // Labels come from each entry, since several
+        // can share an address when some are empty.
+        for ((address, length, span), entry) in assembled.data_layout.iter().zip(&assembled.data_entries) {
+            let start = (address - data_start) as usize;
+            let bytes = &data[start..start + length];
+            let (line, source) = source_text(*span);
+
+            let mut labels = sorted(Some(entry.labels.iter().map(|label| label_name(&label.name)).collect()));
+            let mut line = Some((line, source));
+
+            // Empty data still gets a line, for its labels and source.
+            let mut chunks: Vec<_> = bytes.chunks(BYTES_PER_LINE).collect();
+            if chunks.is_empty() {
+                chunks.push(&[]);
+            }
+
+            let mut chunk_address = *address;
+            for chunk in chunks {
+                let (line, source) = line.take().unwrap_or_default();
+                lines.push(RocAsmListingLine {
+                    location: RocAsmListingLocation::Address(chunk_address),
+                    bytes: chunk.to_vec(),
+                    labels: std::mem::take(&mut labels),
+                    line,
+                    source,
+                });
+                chunk_address = chunk_address.wrapping_add(chunk.len() as u16);
+            }
+        }
+
+        Self { lines }
+    }
+}
+
+impl fmt::Display for RocAsmListing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let labels_width = self.lines.iter()
+            .map(|line| line.labels.join(" ").len())
+            .max()
+            .unwrap_or(0);
+
+        for line in &self.lines {
+            let bytes: Vec<_> = line.bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let number = match line.line {
+                0 => String::new(),
+                number => number.to_string(),
+            };
+
+            let text = format!(
+                "{}  {:<width$}  {:<lw$}  {:>5}  {}",
+                line.location,
+                bytes.join(" "),
+                line.labels.join(" "),
+                number,
+                line.source,
+                width = BYTES_PER_LINE * 3 - 1,
+                lw = labels_width,
+            );
+            writeln!(f, "{}", text.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+
+/// How a label's stored name is shown. Anonymous labels
+/// are stored by number, and all of them look like `@@`.
+fn label_name(name: &str) -> String {
+    if name.starts_with('@') {
+        "@@".to_string()
+    } else {
+        format!("@{}", name)
+    }
+}
+
+fn sorted(labels: Option<Vec<String>>) -> Vec<String> {
+    let mut labels = labels.unwrap_or_default();
+    labels.sort();
+    labels
+}
+
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn empty_data_keeps_only_its_own_labels() {
+        let (_, listing) = crate::assemble_with_listing("@e .fill 0, 0; @f .string \"hi\"; EXIT;", None).unwrap();
+        let labels: Vec<_> = listing.lines.iter()
+            .filter(|line| !line.labels.is_empty())
+            .map(|line| (line.line, line.labels.join(" ")))
+            .collect();
+        assert_eq!(labels, [(1, "@e".to_string()), (1, "@f".to_string())]);
+    }
+}
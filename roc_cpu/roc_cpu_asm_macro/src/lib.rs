@@ -0,0 +1,102 @@
+use std::path::Path;
+
+use proc_macro::TokenStream;
+use quote::{quote, quote_spanned, format_ident};
+use roc_cpu_asm::RocAsmWarning;
+use roc_cpu_types::*;
+
+#[proc_macro]
+pub fn roc_asm(input: TokenStream) -> TokenStream {
+
+    // `.incbin` paths are relative to the crate being
+    // compiled, the same as they would be for build scripts.
+    let base_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+
+    let assembled = roc_cpu_asm::assemble_tokens_with_includes(input.into(), Path::new(&base_dir));
+    let (final_program, warnings, included_files) = match assembled {
+        Ok(assembled) => assembled,
+        Err(err) => {
+            let errors = err.to_compile_error();
+            return TokenStream::from(quote! {
+                { #errors RocCPUProgram::default() }
+            });
+        }
+    };
+
+    let instructions = final_program.instructions.iter().map(instruction_to_tokens);
+    let data = final_program.data.iter().map(|segment| {
+        let address = segment.address;
+        let bytes = &segment.bytes;
+        quote! {
+            RocCPUDataSegment {
+                address: #address,
+                bytes: vec![#(#bytes),*],
+            }
+        }
+    });
+
+    let warnings = warnings.iter().enumerate().map(warning_to_tokens);
+
+    // Cargo only knows to rebuild when an `.incbin` file
+    // changes if something includes it.
+    let included_files = included_files.iter().map(|path| {
+        let path = path.to_string_lossy();
+        quote! { const _: &[u8] = include_bytes!(#path); }
+    });
+
+    let q = quote! {
+        {
+            #(#included_files)*
+            #(#warnings)*
+            RocCPUProgram {
+                instructions: vec![
+                    #(#instructions),*
+                ],
+                data: vec![
+                    #(#data),*
+                ],
+            }
+        }
+    };
+    TokenStream::from(q)
+}
+
+/// There's no stable way for a proc macro to emit a
+/// warning, so this uses something deprecated at the
+/// warning's span, and rustc reports it for us.
+fn warning_to_tokens((index, warning): (usize, &RocAsmWarning)) -> proc_macro2::TokenStream {
+    let name = format_ident!("roc_asm_warning_{}", index, span = warning.span);
+    let note = &warning.message;
+    quote_spanned! {warning.span=>
+        #[deprecated(note = #note)]
+        #[allow(non_camel_case_types)]
+        struct #name;
+        let _ = #name;
+    }
+}
+
+/// The Rust expression that builds `instruction`.
+fn instruction_to_tokens(instruction: &RocCPUInstruction) -> proc_macro2::TokenStream {
+    let variant = format_ident!("{}", instruction.variant_name());
+
+    let operands = instruction.operands();
+    if operands.is_empty() {
+        return quote! { RocCPUInstruction::#variant };
+    }
+
+    let operands = operands.into_iter().map(|operand| {
+        match operand {
+            RocCPUOperand::Register(register) => {
+                // Register variants have no fields, so
+                // their Debug output is just their name.
+                let register = format_ident!("{}", format!("{:?}", register));
+                quote! { RocCPURegister::#register }
+            },
+            RocCPUOperand::Byte(value) => quote! { #value },
+        }
+    });
+
+    quote! {
+        RocCPUInstruction::#variant(#(#operands),*)
+    }
+}
@@ -1,5 +1,5 @@
 use proc_macro::TokenStream;
-use quote::{quote, quote_spanned};
+use quote::{quote, quote_spanned, format_ident};
 use syn::*;
 
 
@@ -21,13 +21,63 @@ pub fn create_decoder_derive_additions(input: TokenStream) -> TokenStream {
         }
     };
 
+    let discriminant = quote!{ discriminant };
+    let used_bytes = quote!{ used_bytes };
+
+    // One `if` per variant. Fields are decoded in order,
+    // each from the bytes left over by the one before,
+    // mirroring how ProgramEncodable appends them.
+    let variant_checks = data.variants.into_iter().map(|v| {
+        let variant_ident = v.ident;
+        let value = match v.discriminant {
+            Some((_, value)) => value,
+            None => {
+                return quote_spanned! {variant_ident.span()=>
+                    compile_error!("ProgramDecodable needs every variant to have an explicit discriminant.");
+                };
+            }
+        };
+
+        if v.fields.is_empty() {
+            return quote! {
+                if #discriminant == (#value) {
+                    return Ok((#enum_ident::#variant_ident, 1));
+                }
+            };
+        }
+
+        let letter_params = (0..v.fields.len()).map(|i| {
+            format_ident!("{}", (b'a' + i as u8) as char)
+        }).collect::<Vec<_>>();
+        let field_types = v.fields.iter().map(|f| f.ty.clone());
+
+        quote! {
+            if #discriminant == (#value) {
+                let mut #used_bytes: usize = 1;
+                #(
+                    let (#letter_params, len) =
+                        <#field_types as ProgramDecodable>::decode(&bytes[#used_bytes..])?;
+                    #used_bytes += len;
+                )*
+                return Ok((#enum_ident::#variant_ident(#(#letter_params),*), #used_bytes));
+            }
+        }
+    });
+
     let rebuilt = quote! {
         impl ProgramDecodable for #enum_ident {
-            fn decode(value: u8) -> Self {
-                panic!("Oh no.");
+            fn decode(bytes: &[u8]) -> Result<(Self, usize), ProgramDecodeError> {
+                let #discriminant = match bytes.first() {
+                    Some(value) => *value,
+                    None => return Err(ProgramDecodeError::UnexpectedEnd),
+                };
+
+                #(#variant_checks)*
+
+                Err(ProgramDecodeError::UnknownDiscriminant(#discriminant))
             }
         }
     };
 
     TokenStream::from(rebuilt)
-}
\ No newline at end of file
+}
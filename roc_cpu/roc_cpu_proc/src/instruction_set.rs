@@ -0,0 +1,171 @@
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{quote, quote_spanned, format_ident};
+use syn::*;
+
+
+/// What a variant's `#[mnemonic]` attribute says.
+struct MnemonicAttribute {
+    mnemonic: LitStr,
+
+    /// `address = code` or `address = data`.
+    address: Option<Ident>,
+}
+
+impl MnemonicAttribute {
+    fn parse(attrs: &[Attribute], variant_ident: &Ident) -> Result<Self> {
+        let attr = match attrs.iter().find(|attr| attr.path().is_ident("mnemonic")) {
+            Some(attr) => attr,
+            None => return Err(Error::new(
+                variant_ident.span(),
+                "InstructionSet needs every variant to have a #[mnemonic(\"...\")] attribute."
+            )),
+        };
+
+        attr.parse_args_with(|input: parse::ParseStream| {
+            let mnemonic: LitStr = input.parse()?;
+
+            let mut address = None;
+            if input.peek(Token![,]) {
+                input.parse::<Token![,]>()?;
+                let key: Ident = input.parse()?;
+                if key != "address" {
+                    return Err(Error::new(key.span(), "Expected `address = code` or `address = data`."));
+                }
+                input.parse::<Token![=]>()?;
+                let kind: Ident = input.parse()?;
+                if kind != "code" && kind != "data" {
+                    return Err(Error::new(kind.span(), "Expected `code` or `data`."));
+                }
+                address = Some(kind);
+            }
+
+            Ok(Self { mnemonic, address })
+        })
+    }
+}
+
+
+pub fn create_instruction_set_derive_additions(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let DeriveInput {
+        ident: enum_ident,
+        data,
+        .. } = input;
+
+    let data = match data {
+        Data::Enum(e) => e,
+        _ => {
+            let err = quote_spanned! {enum_ident.span()=>
+                compile_error!("InstructionSet can only be derived on an Enum.");
+            };
+            return TokenStream::from(err);
+        }
+    };
+
+    let mut forms = vec![];
+    let mut variant_names = vec![];
+    let mut mnemonics = vec![];
+    let mut operand_lists = vec![];
+    let mut from_operands = vec![];
+
+    for v in data.variants {
+        let variant_ident = v.ident;
+        let attribute = match MnemonicAttribute::parse(&v.attrs, &variant_ident) {
+            Ok(attribute) => attribute,
+            Err(err) => return TokenStream::from(err.to_compile_error()),
+        };
+
+        let name = variant_ident.to_string();
+        let mnemonic = attribute.mnemonic;
+        let field_types: Vec<_> = v.fields.iter().map(|f| f.ty.clone()).collect();
+
+        let address = match attribute.address {
+            Some(kind) if field_types.len() < 2 => {
+                return TokenStream::from(quote_spanned! {kind.span()=>
+                    compile_error!("Only variants with a hi and lo byte can take an address.");
+                });
+            },
+            Some(kind) if kind == "code" => quote! { Some(RocCPUAddressKind::Code) },
+            Some(_) => quote! { Some(RocCPUAddressKind::Data) },
+            None => quote! { None },
+        };
+
+        forms.push(quote! {
+            RocCPUInstructionForm {
+                mnemonic: #mnemonic,
+                variant: #name,
+                operands: &[#(<#field_types as RocCPUOperandType>::KIND),*],
+                address: #address,
+            }
+        });
+
+        variant_names.push(quote! { Self::#variant_ident { .. } => #name });
+        mnemonics.push(quote! { Self::#variant_ident { .. } => #mnemonic });
+
+        if field_types.is_empty() {
+            operand_lists.push(quote! { Self::#variant_ident => vec![] });
+            from_operands.push(quote! { (#name, []) => Some(Self::#variant_ident) });
+            continue;
+        }
+
+        let letter_params = (0..field_types.len()).map(|i| {
+            format_ident!("{}", (b'a' + i as u8) as char, span = Span::call_site())
+        }).collect::<Vec<_>>();
+
+        operand_lists.push(quote! {
+            Self::#variant_ident(#(#letter_params),*) => vec![#(RocCPUOperandType::to_operand(#letter_params)),*]
+        });
+        from_operands.push(quote! {
+            (#name, [#(#letter_params),*]) => Some(Self::#variant_ident(
+                #(<#field_types as RocCPUOperandType>::from_operand(*#letter_params)?),*
+            ))
+        });
+    }
+
+    let rebuilt = quote! {
+        impl #enum_ident {
+            /// Every way an instruction can be written in
+            /// assembly, one for each variant, in order.
+            pub const FORMS: &'static [RocCPUInstructionForm] = &[
+                #(#forms),*
+            ];
+
+            /// The name of this instruction's variant,
+            /// e.g. `"JumpIfZero"`.
+            pub fn variant_name(&self) -> &'static str {
+                match self {
+                    #(#variant_names),*
+                }
+            }
+
+            /// The assembly mnemonic for this instruction,
+            /// e.g. `"JZ"`.
+            pub fn mnemonic(&self) -> &'static str {
+                match self {
+                    #(#mnemonics),*
+                }
+            }
+
+            /// This instruction's operands, in order.
+            pub fn operands(&self) -> Vec<RocCPUOperand> {
+                match *self {
+                    #(#operand_lists),*
+                }
+            }
+
+            /// Builds the variant named `variant` from its
+            /// operands, or returns `None` if they don't
+            /// match the variant's fields.
+            pub fn from_operands(variant: &str, operands: &[RocCPUOperand]) -> Option<Self> {
+                match (variant, operands) {
+                    #(#from_operands,)*
+                    _ => None,
+                }
+            }
+        }
+    };
+
+    TokenStream::from(rebuilt)
+}
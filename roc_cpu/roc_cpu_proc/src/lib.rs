@@ -1,10 +1,8 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::parse_macro_input;
 
 mod decode;
 mod encode;
-mod language;
+mod instruction_set;
 
 #[proc_macro_derive(ProgramEncodable)]
 pub fn encodable_derive(item: TokenStream) -> TokenStream {
@@ -16,23 +14,7 @@ pub fn decodable_derive(item: TokenStream) -> TokenStream {
     decode::create_decoder_derive_additions(item)
 }
 
-#[proc_macro]
-pub fn roc_asm(input: TokenStream) -> TokenStream {
-    
-    let token_data = parse_macro_input!(input as language::Program);
-    let labels = token_data.clone().labels;
-
-    
-    let final_program = token_data.clone().operations.into_iter().filter_map(
-        move |op| {
-            language::translate_asm_to_opcode(op, &labels)
-        }
-    );
-    
-    let q = quote! {
-        vec![
-            #(#final_program),*
-        ]
-    };
-    TokenStream::from(q)
+#[proc_macro_derive(InstructionSet, attributes(mnemonic))]
+pub fn instruction_set_derive(item: TokenStream) -> TokenStream {
+    instruction_set::create_instruction_set_derive_additions(item)
 }
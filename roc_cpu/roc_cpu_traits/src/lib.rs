@@ -15,15 +15,47 @@ impl ProgramEncodable for u8 {
 }
 
 
+/// Why some bytes couldn't be decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgramDecodeError {
+    /// The bytes ran out part way through a value.
+    UnexpectedEnd,
+
+    /// A discriminant that no variant of the enum
+    /// being decoded uses.
+    UnknownDiscriminant(u8),
+}
+
+impl std::fmt::Display for ProgramDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "unexpected end of input"),
+            Self::UnknownDiscriminant(value) => {
+                write!(f, "unknown discriminant {:#04x}", value)
+            },
+        }
+    }
+}
+
+impl std::error::Error for ProgramDecodeError {}
+
+
 pub trait ProgramDecodable where
     Self: Sized
 {
-    fn decode(value: u8) -> Self;
+    /// Decodes a value from the start of `bytes`, the
+    /// reverse of `ProgramEncodable::encode`. Returns the
+    /// value and how many bytes it took up, so that the
+    /// next value can be decoded from the rest.
+    fn decode(bytes: &[u8]) -> Result<(Self, usize), ProgramDecodeError>;
 }
 
 impl ProgramDecodable for u8 {
-    fn decode(value: u8) -> Self {
-        value
+    fn decode(bytes: &[u8]) -> Result<(Self, usize), ProgramDecodeError> {
+        match bytes.first() {
+            Some(value) => Ok((*value, 1)),
+            None => Err(ProgramDecodeError::UnexpectedEnd),
+        }
     }
 }
 
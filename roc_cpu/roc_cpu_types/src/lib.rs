@@ -0,0 +1,443 @@
+use roc_cpu_proc::*;
+use roc_cpu_traits::*;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ProgramEncodable, ProgramDecodable)]
+pub enum RocCPURegister {
+    GeneralPurposeA = 0x1,
+    GeneralPurposeB = 0x2,
+    GeneralPurposeC = 0x3,
+    GeneralPurposeD = 0x4,
+
+    ReturnValue = 0x10,
+
+    FunctionParameter1 = 0x20,
+    FunctionParameter2 = 0x21,
+    FunctionParameter3 = 0x22,
+    FunctionParameter4 = 0x23,
+
+    FunctionReturn = 0x30,
+}
+
+impl RocCPURegister {
+    /// Every register, in the order `RocCPUState`
+    /// stores them.
+    pub const ALL: [RocCPURegister; 10] = [
+        Self::GeneralPurposeA,
+        Self::GeneralPurposeB,
+        Self::GeneralPurposeC,
+        Self::GeneralPurposeD,
+        Self::ReturnValue,
+        Self::FunctionParameter1,
+        Self::FunctionParameter2,
+        Self::FunctionParameter3,
+        Self::FunctionParameter4,
+        Self::FunctionReturn,
+    ];
+}
+
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ProgramEncodable, ProgramDecodable, InstructionSet)]
+pub enum RocCPUInstruction {
+    #[mnemonic("ADD")]
+    Add(RocCPURegister, RocCPURegister) = 0x1,
+    #[mnemonic("ADDI")]
+    AddI(RocCPURegister, u8) = 0x2,
+    #[mnemonic("SUB")]
+    Sub(RocCPURegister, RocCPURegister) = 0x3,
+    #[mnemonic("SUBI")]
+    SubI(RocCPURegister, u8) = 0x4,
+    #[mnemonic("MUL")]
+    Mul(RocCPURegister, RocCPURegister) = 0x5,
+    #[mnemonic("MULI")]
+    MulI(RocCPURegister, u8) = 0x6,
+    #[mnemonic("DIV")]
+    Div(RocCPURegister, RocCPURegister) = 0x7,
+    #[mnemonic("DIVI")]
+    DivI(RocCPURegister, u8) = 0x8,
+
+    #[mnemonic("SETRET")]
+    SetRet(u8) = 0x20,
+    #[mnemonic("PUT")]
+    Put(RocCPURegister, u8) = 0x21,
+    #[mnemonic("MOV")]
+    Mov(RocCPURegister, RocCPURegister) = 0x22,
+
+    // Puts value (arg3) into memory at 0x<arg1><arg2>
+    #[mnemonic("PUTMEM", address = data)]
+    PutMem(u8, u8, u8) = 0x40,
+    #[mnemonic("PUSH")]
+    Push(RocCPURegister) = 0x41,
+    #[mnemonic("POP")]
+    Pop(RocCPURegister) = 0x42,
+
+    // Loads arg1 from memory at 0x<arg2><arg3>
+    #[mnemonic("LOAD", address = data)]
+    Load(RocCPURegister, u8, u8) = 0x43,
+    // Stores arg3 into memory at 0x<arg1><arg2>
+    #[mnemonic("STORE", address = data)]
+    Store(u8, u8, RocCPURegister) = 0x44,
+    // As above, but the address is the value of the
+    // register pair <hi register><lo register>
+    #[mnemonic("LOAD")]
+    LoadIndirect(RocCPURegister, RocCPURegister, RocCPURegister) = 0x45,
+    #[mnemonic("STORE")]
+    StoreIndirect(RocCPURegister, RocCPURegister, RocCPURegister) = 0x46,
+    // As above, then add one to the register pair
+    #[mnemonic("LOADINC")]
+    LoadIndirectInc(RocCPURegister, RocCPURegister, RocCPURegister) = 0x47,
+    #[mnemonic("STOREINC")]
+    StoreIndirectInc(RocCPURegister, RocCPURegister, RocCPURegister) = 0x48,
+
+    #[mnemonic("EXIT")]
+    Exit = 0x80,
+    #[mnemonic("NOP")]
+    Nop = 0x81,
+    #[mnemonic("CMP")]
+    Cmp(RocCPURegister, RocCPURegister) = 0x82,
+
+    // Conditional jumps read the flags set by the most
+    // recent ALU operation. Comparisons are unsigned, so
+    // after `Cmp(a, b)`, `JumpIfLess` jumps when a < b.
+    #[mnemonic("JUMP", address = code)]
+    Jump(u8, u8) = 0xA0,
+    #[mnemonic("JZ", address = code)]
+    JumpIfZero(u8, u8) = 0xA1,
+    #[mnemonic("JNZ", address = code)]
+    JumpIfNotZero(u8, u8) = 0xA2,
+    #[mnemonic("JC", address = code)]
+    JumpIfCarry(u8, u8) = 0xA3,
+    #[mnemonic("JNC", address = code)]
+    JumpIfNotCarry(u8, u8) = 0xA4,
+    #[mnemonic("JN", address = code)]
+    JumpIfNegative(u8, u8) = 0xA5,
+    #[mnemonic("JGT", address = code)]
+    JumpIfGreater(u8, u8) = 0xA6,
+    #[mnemonic("JLT", address = code)]
+    JumpIfLess(u8, u8) = 0xA7,
+    #[mnemonic("JGE", address = code)]
+    JumpIfGreaterOrEqual(u8, u8) = 0xA8,
+    #[mnemonic("JLE", address = code)]
+    JumpIfLessOrEqual(u8, u8) = 0xA9,
+
+    #[mnemonic("CALL", address = code)]
+    Call(u8, u8) = 0xB0,
+    #[mnemonic("RETURN")]
+    Return = 0xB1,
+
+    #[mnemonic("RENDER")]
+    Render = 0xF0,
+    #[mnemonic("WAIT")]
+    Wait(u8) = 0xF1,
+}
+
+
+impl RocCPUInstruction {
+    /// The `hi, lo` address this instruction jumps or
+    /// calls to, if it has one.
+    pub fn jump_target(&self) -> Option<u16> {
+        use RocCPUInstruction::*;
+
+        match *self {
+            Jump(hi, lo)
+            | JumpIfZero(hi, lo)
+            | JumpIfNotZero(hi, lo)
+            | JumpIfCarry(hi, lo)
+            | JumpIfNotCarry(hi, lo)
+            | JumpIfNegative(hi, lo)
+            | JumpIfGreater(hi, lo)
+            | JumpIfLess(hi, lo)
+            | JumpIfGreaterOrEqual(hi, lo)
+            | JumpIfLessOrEqual(hi, lo)
+            | Call(hi, lo) => Some(u16::from_be_bytes([hi, lo])),
+            _ => None,
+        }
+    }
+
+    /// This instruction with its jump or call target
+    /// replaced. Other instructions are returned as-is.
+    pub fn with_jump_target(self, target: u16) -> Self {
+        use RocCPUInstruction::*;
+
+        let [hi, lo] = target.to_be_bytes();
+        match self {
+            Jump(..) => Jump(hi, lo),
+            JumpIfZero(..) => JumpIfZero(hi, lo),
+            JumpIfNotZero(..) => JumpIfNotZero(hi, lo),
+            JumpIfCarry(..) => JumpIfCarry(hi, lo),
+            JumpIfNotCarry(..) => JumpIfNotCarry(hi, lo),
+            JumpIfNegative(..) => JumpIfNegative(hi, lo),
+            JumpIfGreater(..) => JumpIfGreater(hi, lo),
+            JumpIfLess(..) => JumpIfLess(hi, lo),
+            JumpIfGreaterOrEqual(..) => JumpIfGreaterOrEqual(hi, lo),
+            JumpIfLessOrEqual(..) => JumpIfLessOrEqual(hi, lo),
+            Call(..) => Call(hi, lo),
+            other => other,
+        }
+    }
+}
+
+
+/// Bytes a program expects to find in memory at
+/// `address` when it starts, from `.byte` and friends.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RocCPUDataSegment {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+}
+
+/// An assembled program: its instructions, and the
+/// initialised data that is copied into memory when
+/// it is loaded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RocCPUProgram {
+    pub instructions: Vec<RocCPUInstruction>,
+    pub data: Vec<RocCPUDataSegment>,
+}
+
+impl From<Vec<RocCPUInstruction>> for RocCPUProgram {
+    fn from(instructions: Vec<RocCPUInstruction>) -> Self {
+        Self { instructions, data: vec![] }
+    }
+}
+
+impl std::ops::Deref for RocCPUProgram {
+    type Target = [RocCPUInstruction];
+
+    fn deref(&self) -> &Self::Target {
+        &self.instructions
+    }
+}
+
+
+/// Encodes `program` into the bytes that should be placed
+/// in memory at `load_address`.
+///
+/// `roc_asm!` resolves labels to instruction indexes, so
+/// every jump and call target that points inside the
+/// program is rewritten to the byte address that
+/// instruction ends up at.
+pub fn encode_program(program: &[RocCPUInstruction], load_address: u16) -> Vec<u8> {
+    let mut addresses = Vec::with_capacity(program.len() + 1);
+    let mut address = load_address as usize;
+    for instruction in program {
+        addresses.push(address);
+        address += instruction.encode().len();
+    }
+    addresses.push(address);
+
+    let mut output = vec![];
+    for instruction in program {
+        let instruction = match instruction.jump_target() {
+            Some(target) if (target as usize) < addresses.len() => {
+                instruction.with_jump_target(addresses[target as usize] as u16)
+            },
+            _ => *instruction,
+        };
+        output.append(&mut instruction.encode());
+    }
+    output
+}
+
+
+/// One operand of an instruction, as written
+/// in assembly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RocCPUOperand {
+    Register(RocCPURegister),
+    Byte(u8),
+}
+
+/// The kind of operand an instruction expects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RocCPUOperandKind {
+    Register,
+    Byte,
+}
+
+/// What the `hi, lo` address an instruction takes
+/// points at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RocCPUAddressKind {
+    /// An instruction, written as a code label.
+    Code,
+    /// Memory, written as a data label.
+    Data,
+}
+
+/// One way an instruction can be written in assembly,
+/// generated from the `#[mnemonic]` on its variant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RocCPUInstructionForm {
+    pub mnemonic: &'static str,
+    pub variant: &'static str,
+    pub operands: &'static [RocCPUOperandKind],
+
+    /// Set when the instruction's first two byte operands
+    /// are a `hi, lo` address, which can then also be
+    /// written as a single label or expression.
+    pub address: Option<RocCPUAddressKind>,
+}
+
+impl RocCPUInstructionForm {
+    /// Where the `hi` byte of this form's address is
+    /// among its operands, if it takes one.
+    pub fn address_index(&self) -> Option<usize> {
+        self.address?;
+        self.operands.windows(2)
+            .position(|pair| pair == [RocCPUOperandKind::Byte, RocCPUOperandKind::Byte])
+    }
+}
+
+/// A type that can be an instruction's operand.
+pub trait RocCPUOperandType: Sized {
+    const KIND: RocCPUOperandKind;
+
+    fn to_operand(self) -> RocCPUOperand;
+    fn from_operand(operand: RocCPUOperand) -> Option<Self>;
+}
+
+impl RocCPUOperandType for RocCPURegister {
+    const KIND: RocCPUOperandKind = RocCPUOperandKind::Register;
+
+    fn to_operand(self) -> RocCPUOperand {
+        RocCPUOperand::Register(self)
+    }
+
+    fn from_operand(operand: RocCPUOperand) -> Option<Self> {
+        match operand {
+            RocCPUOperand::Register(register) => Some(register),
+            RocCPUOperand::Byte(_) => None,
+        }
+    }
+}
+
+impl RocCPUOperandType for u8 {
+    const KIND: RocCPUOperandKind = RocCPUOperandKind::Byte;
+
+    fn to_operand(self) -> RocCPUOperand {
+        RocCPUOperand::Byte(self)
+    }
+
+    fn from_operand(operand: RocCPUOperand) -> Option<Self> {
+        match operand {
+            RocCPUOperand::Byte(value) => Some(value),
+            RocCPUOperand::Register(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RocCPURegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use RocCPURegister::*;
+
+        let name = match self {
+            GeneralPurposeA => "ax",
+            GeneralPurposeB => "bx",
+            GeneralPurposeC => "cx",
+            GeneralPurposeD => "dx",
+            ReturnValue => "ret",
+            FunctionParameter1 => "f1",
+            FunctionParameter2 => "f2",
+            FunctionParameter3 => "f3",
+            FunctionParameter4 => "f4",
+            FunctionReturn => "fret",
+        };
+        write!(f, "${}", name)
+    }
+}
+
+impl std::fmt::Display for RocCPUOperand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Register(register) => write!(f, "{}", register),
+            Self::Byte(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Writes the instruction as `roc_asm!` would read it,
+/// without the closing `;`. Jump and call targets are
+/// written as their `hi, lo` bytes.
+impl std::fmt::Display for RocCPUInstruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.mnemonic())?;
+        for (i, operand) in self.operands().iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, operand)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every form, with operands that differ from each
+    /// other so that any mix-up in their order shows.
+    fn every_instruction() -> Vec<RocCPUInstruction> {
+        RocCPUInstruction::FORMS.iter()
+            .map(|form| {
+                let operands: Vec<_> = form.operands.iter()
+                    .enumerate()
+                    .map(|(index, kind)| match kind {
+                        RocCPUOperandKind::Register => {
+                            RocCPUOperand::Register(RocCPURegister::ALL[index % RocCPURegister::ALL.len()])
+                        },
+                        RocCPUOperandKind::Byte => RocCPUOperand::Byte(0x12 + index as u8),
+                    })
+                    .collect();
+                RocCPUInstruction::from_operands(form.variant, &operands)
+                    .unwrap_or_else(|| panic!("{} doesn't take {:?}", form.variant, operands))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_instruction_round_trips() {
+        for instruction in every_instruction() {
+            let bytes = instruction.encode();
+            assert_eq!(RocCPUInstruction::decode(&bytes), Ok((instruction, bytes.len())));
+        }
+    }
+
+    #[test]
+    fn decoding_stops_at_the_end_of_the_instruction() {
+        let mut bytes = RocCPUInstruction::Push(RocCPURegister::GeneralPurposeA).encode();
+        let length = bytes.len();
+        bytes.extend(RocCPUInstruction::Exit.encode());
+        assert_eq!(
+            RocCPUInstruction::decode(&bytes),
+            Ok((RocCPUInstruction::Push(RocCPURegister::GeneralPurposeA), length))
+        );
+    }
+
+    #[test]
+    fn truncated_instructions_are_unexpected_ends() {
+        for instruction in every_instruction() {
+            let bytes = instruction.encode();
+            for length in 0..bytes.len() {
+                assert_eq!(
+                    RocCPUInstruction::decode(&bytes[..length]),
+                    Err(ProgramDecodeError::UnexpectedEnd),
+                    "{} cut to {} bytes", instruction, length
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_opcodes_are_unknown_discriminants() {
+        assert_eq!(RocCPUInstruction::decode(&[0xFF]), Err(ProgramDecodeError::UnknownDiscriminant(0xFF)));
+    }
+
+    #[test]
+    fn unknown_registers_are_unknown_discriminants() {
+        let mut bytes = RocCPUInstruction::Push(RocCPURegister::GeneralPurposeA).encode();
+        bytes[1] = 0x99;
+        assert_eq!(RocCPUInstruction::decode(&bytes), Err(ProgramDecodeError::UnknownDiscriminant(0x99)));
+    }
+}
@@ -0,0 +1,541 @@
+//! A Debug Adapter Protocol server over stdin and stdout,
+//! so that editors can debug Roc assembly.
+//!
+//! A client launches a program with `"program"` (a `.rasm`
+//! file), and optionally `"stopOnEntry"` and `"loadAddress"`.
+//! There's one thread, and one stack frame for each call
+//! the program is in.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use roc_cpu::*;
+use serde_json::{json, Value};
+
+use crate::debugger::parse_number;
+use crate::program::LoadedProgram;
+use crate::stepping::{Stepper, StopWhen, Stopped};
+
+/// The only thread a Roc program has.
+const THREAD_ID: u64 = 1;
+
+/// How many bytes each entry in the memory scope shows.
+const MEMORY_PREVIEW_LENGTH: usize = 16;
+
+/// References for the scopes in the variables view.
+const REGISTERS_REFERENCE: u64 = 1;
+const FLAGS_REFERENCE: u64 = 2;
+const STACK_REFERENCE: u64 = 3;
+const MEMORY_REFERENCE: u64 = 4;
+
+/// What to do once a request has been responded to.
+enum After {
+    Nothing,
+    /// Tell the client it can send breakpoints.
+    Initialized,
+    /// Start running, or stop on entry.
+    Start,
+    Resume(StopWhen),
+    Terminated,
+    /// End the session.
+    Disconnect,
+}
+
+/// The program being debugged, once the client has
+/// launched it.
+struct Session {
+    program: LoadedProgram,
+    runner: RocCPURunner,
+    stepper: Stepper,
+    stop_on_entry: bool,
+
+    /// Set once the program has exited or faulted,
+    /// after which it can't be resumed.
+    ended: bool,
+}
+
+pub struct DapServer {
+    messages: Receiver<Value>,
+
+    /// Requests that arrived while the program was
+    /// running, to handle once it stops.
+    deferred: VecDeque<Value>,
+
+    /// The sequence number of the next message we send.
+    seq: u64,
+    session: Option<Session>,
+}
+
+impl DapServer {
+    pub fn new() -> Self {
+        Self {
+            messages: read_messages(),
+            deferred: VecDeque::new(),
+            seq: 1,
+            session: None,
+        }
+    }
+
+    /// Handles requests until the client disconnects
+    /// or hangs up.
+    pub fn serve(&mut self) {
+        while let Some(request) = self.next_request() {
+            if !self.handle(&request) {
+                break;
+            }
+        }
+    }
+
+    fn next_request(&mut self) -> Option<Value> {
+        match self.deferred.pop_front() {
+            Some(request) => Some(request),
+            None => self.messages.recv().ok(),
+        }
+    }
+
+    /// Responds to one request. Returns false once
+    /// the session is over.
+    fn handle(&mut self, request: &Value) -> bool {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+
+        let after = match self.respond_to(command, arguments) {
+            Ok((body, after)) => {
+                self.respond(request, Ok(body));
+                after
+            },
+            Err(message) => {
+                self.respond(request, Err(message));
+                After::Nothing
+            },
+        };
+
+        match after {
+            After::Nothing => {},
+            After::Initialized => self.event("initialized", json!({})),
+            After::Start => self.start(),
+            After::Resume(stop_when) => self.resume(stop_when),
+            After::Terminated => self.event("terminated", json!({})),
+            After::Disconnect => return false,
+        }
+        true
+    }
+
+    fn respond_to(&mut self, command: &str, arguments: &Value) -> Result<(Value, After), String> {
+        let body = match command {
+            "initialize" => json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsReadMemoryRequest": true,
+                "supportsTerminateRequest": true,
+            }),
+            "launch" => {
+                self.launch(arguments)?;
+                return Ok((json!({}), After::Initialized));
+            },
+            "setBreakpoints" => self.set_breakpoints(arguments)?,
+            // Faults always stop the program.
+            "setExceptionBreakpoints" => json!({}),
+            "configurationDone" => return Ok((json!({}), After::Start)),
+            "threads" => json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] }),
+            "stackTrace" => self.stack_trace()?,
+            "scopes" => json!({
+                "scopes": [
+                    scope("Registers", REGISTERS_REFERENCE),
+                    scope("Flags", FLAGS_REFERENCE),
+                    scope("Stack", STACK_REFERENCE),
+                    scope("Memory", MEMORY_REFERENCE),
+                ],
+            }),
+            "variables" => self.variables(arguments)?,
+            "readMemory" => self.read_memory(arguments)?,
+            "continue" => {
+                return Ok((json!({ "allThreadsContinued": true }), After::Resume(StopWhen::Never)));
+            },
+            "next" => return Ok((json!({}), After::Resume(StopWhen::SteppedOver))),
+            "stepIn" => return Ok((json!({}), After::Resume(StopWhen::Stepped))),
+            "stepOut" => return Ok((json!({}), After::Resume(StopWhen::Returned))),
+            // Only reached while the program is already stopped.
+            "pause" => json!({}),
+            "terminate" => {
+                if let Some(session) = self.session.as_mut() {
+                    session.ended = true;
+                }
+                return Ok((json!({}), After::Terminated));
+            },
+            "disconnect" => return Ok((json!({}), After::Disconnect)),
+            _ => return Err(format!("roc_debug doesn't support \"{}\".", command)),
+        };
+        Ok((body, After::Nothing))
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<(), String> {
+        let path = arguments["program"].as_str()
+            .ok_or("launch needs a \"program\" to debug.")?;
+        let load_address = match &arguments["loadAddress"] {
+            Value::Null => None,
+            value => {
+                let address = match value {
+                    Value::Number(number) => number.as_u64().map(|address| address as usize),
+                    Value::String(text) => parse_number(text),
+                    _ => None,
+                };
+                let address = address.and_then(|address| u16::try_from(address).ok())
+                    .ok_or("\"loadAddress\" is not an address in memory.")?;
+                Some(address)
+            },
+        };
+
+        let program = LoadedProgram::open(Path::new(path), load_address)
+            .map_err(|message| format!("Could not load {}:\n{}", path, message))?;
+        self.session = Some(Session {
+            runner: program.runner(),
+            program,
+            stepper: Stepper::default(),
+            stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+            ended: false,
+        });
+        Ok(())
+    }
+
+    /// Replaces every breakpoint with ones on the given
+    /// lines. A line without an instruction gets its
+    /// breakpoint moved to the next one that has one.
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let session = self.session.as_mut().ok_or("There's no program launched.")?;
+        let lines = arguments["breakpoints"].as_array().cloned().unwrap_or_default();
+        let in_program = arguments["source"]["path"].as_str()
+            .is_some_and(|path| same_file(Path::new(path), &session.program.path));
+
+        session.runner.clear_breakpoints();
+        let breakpoints: Vec<_> = lines.iter()
+            .map(|breakpoint| {
+                let line = breakpoint["line"].as_u64().unwrap_or_default() as usize;
+                match session.program.instruction_on_line(line).filter(|_| in_program) {
+                    Some((pc, line)) => {
+                        session.runner.add_breakpoint(pc);
+                        json!({ "verified": true, "line": line })
+                    },
+                    None => json!({
+                        "verified": false,
+                        "line": line,
+                        "message": "There's no instruction here.",
+                    }),
+                }
+            })
+            .collect();
+
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// One frame for where the program is, then one for
+    /// each call it's in, innermost first.
+    fn stack_trace(&self) -> Result<Value, String> {
+        let session = self.session.as_ref().ok_or("There's no program launched.")?;
+        let program = &session.program;
+
+        let pcs = std::iter::once(session.runner.program_counter())
+            .chain(session.stepper.calls.iter().rev().copied());
+        let frames: Vec<_> = pcs.enumerate()
+            .map(|(id, pc)| {
+                let name = match program.routine_at(pc) {
+                    Some(routine) => format!("@{}", routine),
+                    None => format!("{:#06x}", pc),
+                };
+                let mut frame = json!({
+                    "id": id,
+                    "name": name,
+                    "line": program.source_lines.get(&pc).copied().unwrap_or(0),
+                    "column": 0,
+                    "instructionPointerReference": format!("{:#06x}", pc),
+                });
+                if !program.source_lines.is_empty() {
+                    frame["source"] = source(&program.path);
+                    frame["column"] = json!(1);
+                }
+                frame
+            })
+            .collect();
+
+        Ok(json!({ "totalFrames": frames.len(), "stackFrames": frames }))
+    }
+
+    fn variables(&self, arguments: &Value) -> Result<Value, String> {
+        let session = self.session.as_ref().ok_or("There's no program launched.")?;
+        let runner = &session.runner;
+        let state = runner.state();
+
+        let variables: Vec<_> = match arguments["variablesReference"].as_u64() {
+            Some(REGISTERS_REFERENCE) => {
+                let mut registers: Vec<_> = RocCPURegister::ALL.iter()
+                    .map(|register| {
+                        let value = state.register(*register);
+                        variable(&register.to_string(), format!("{:#04x} ({})", value, value))
+                    })
+                    .collect();
+                registers.push(variable("pc", format!("{:#06x}", state.program_counter)));
+                registers.push(variable("sp", state.stack_pointer.to_string()));
+                registers
+            },
+            Some(FLAGS_REFERENCE) => {
+                let flags = state.flags;
+                vec![
+                    variable("zero", flags.zero.to_string()),
+                    variable("carry", flags.carry.to_string()),
+                    variable("negative", flags.negative.to_string()),
+                    variable("overflow", flags.overflow.to_string()),
+                ]
+            },
+            // Top of the stack first.
+            Some(STACK_REFERENCE) => runner.stack().iter().enumerate().rev()
+                .map(|(index, value)| variable(&format!("[{}]", index), format!("{:#04x} ({})", value, value)))
+                .collect(),
+            Some(MEMORY_REFERENCE) => {
+                let mut labels: Vec<_> = session.program.data_labels.iter().collect();
+                labels.sort_by_key(|(_, address)| **address);
+
+                let memory = runner.memory();
+                labels.into_iter()
+                    .map(|(name, address)| {
+                        let start = *address as usize;
+                        let end = (start + MEMORY_PREVIEW_LENGTH).min(memory.len());
+                        let hex: Vec<_> = memory[start..end].iter()
+                            .map(|byte| format!("{:02x}", byte))
+                            .collect();
+                        let mut entry = variable(&format!("@{}", name), hex.join(" "));
+                        entry["memoryReference"] = json!(format!("{:#06x}", start));
+                        entry
+                    })
+                    .collect()
+            },
+            _ => return Err("There are no variables with that reference.".to_string()),
+        };
+
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn read_memory(&self, arguments: &Value) -> Result<Value, String> {
+        let session = self.session.as_ref().ok_or("There's no program launched.")?;
+        let reference = arguments["memoryReference"].as_str().unwrap_or_default();
+        let base = parse_number(reference)
+            .ok_or_else(|| format!("\"{}\" is not an address.", reference))?;
+        let offset = arguments["offset"].as_i64().unwrap_or(0);
+        let start = i64::try_from(base).unwrap_or(i64::MAX).saturating_add(offset);
+        let count = usize::try_from(arguments["count"].as_u64().unwrap_or(0)).unwrap_or(usize::MAX);
+
+        let memory = session.runner.memory();
+        let start = start.clamp(0, memory.len() as i64) as usize;
+        let end = start.saturating_add(count).min(memory.len());
+        let read = end - start;
+
+        Ok(json!({
+            "address": format!("{:#06x}", start),
+            "data": base64(&memory[start..end]),
+            "unreadableBytes": count - read,
+        }))
+    }
+
+    /// Once the client has sent its breakpoints.
+    fn start(&mut self) {
+        let Some(session) = self.session.as_ref() else {
+            return;
+        };
+
+        // Resuming steps before checking for breakpoints,
+        // so one on the first instruction is handled here.
+        if session.stop_on_entry {
+            self.stopped("entry", None);
+        } else if session.runner.is_breakpoint(session.runner.program_counter()) {
+            self.stopped("breakpoint", None);
+        } else {
+            self.resume(StopWhen::Never);
+        }
+    }
+
+    fn resume(&mut self, stop_when: StopWhen) {
+        let Some(session) = self.session.as_mut() else {
+            return;
+        };
+        if session.ended {
+            self.event("terminated", json!({}));
+            return;
+        }
+
+        // While the program runs, anything other than a
+        // request to stop it waits until it has.
+        let messages = &self.messages;
+        let deferred = &mut self.deferred;
+        let mut pause = None;
+        let stopped = session.stepper.resume(&mut session.runner, stop_when, || {
+            loop {
+                match messages.try_recv() {
+                    Ok(message) if message["command"] == "pause" => {
+                        pause = Some(message);
+                        return true;
+                    },
+                    Ok(message) => {
+                        let stop = message["command"] == "disconnect" || message["command"] == "terminate";
+                        deferred.push_back(message);
+                        if stop {
+                            return true;
+                        }
+                    },
+                    Err(TryRecvError::Empty) => return false,
+                    Err(TryRecvError::Disconnected) => return true,
+                }
+            }
+        });
+
+        let exit_code = session.runner.state().return_value();
+        session.ended = matches!(stopped, Stopped::Exited | Stopped::Faulted(_));
+
+        let paused = pause.is_some();
+        if let Some(request) = pause {
+            self.respond(&request, Ok(json!({})));
+        }
+
+        match stopped {
+            Stopped::Finished => self.stopped("step", None),
+            Stopped::Breakpoint => self.stopped("breakpoint", None),
+            // Otherwise the client is leaving.
+            Stopped::Interrupted if paused => self.stopped("pause", None),
+            Stopped::Interrupted => {},
+            Stopped::Exited => {
+                self.event("exited", json!({ "exitCode": exit_code }));
+                self.event("terminated", json!({}));
+            },
+            Stopped::Faulted(fault) => {
+                let description = format!("The program faulted: {}.", fault);
+                self.event("output", json!({ "category": "stderr", "output": format!("{}\n", description) }));
+                self.stopped("exception", Some(description));
+            },
+        }
+    }
+
+    fn stopped(&mut self, reason: &str, description: Option<String>) {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(description) = description {
+            body["description"] = json!(description.clone());
+            body["text"] = json!(description);
+        }
+        self.event("stopped", body);
+    }
+
+    fn respond(&mut self, request: &Value, body: Result<Value, String>) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response);
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn send(&mut self, mut message: Value) {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+
+        let body = message.to_string();
+        let mut stdout = io::stdout().lock();
+        // The client has gone if this fails, which the
+        // reader thread will notice.
+        let _ = write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = stdout.flush();
+    }
+}
+
+
+/// Reads messages on a thread of their own, so that
+/// a running program can be paused.
+fn read_messages() -> Receiver<Value> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        while let Ok(Some(message)) = read_message(&mut stdin) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Reads one message, framed by a `Content-Length`
+/// header. Returns `None` once the client hangs up.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim();
+        if header.is_empty() && length.is_some() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or_default()];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn scope(name: &str, reference: u64) -> Value {
+    json!({ "name": name, "variablesReference": reference, "expensive": false })
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+fn source(path: &Path) -> Value {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    json!({ "name": name, "path": path.to_string_lossy() })
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Standard, padded base64, which is how `readMemory`
+/// sends bytes.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut output = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for index in 0..4 {
+            if index <= chunk.len() {
+                output.push(ALPHABET[(group >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
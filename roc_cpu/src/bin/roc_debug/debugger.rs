@@ -0,0 +1,311 @@
+use roc_cpu::*;
+
+use crate::program::LoadedProgram;
+use crate::stepping::{Stepper, StopWhen, Stopped};
+
+/// How many instructions `disassemble` shows
+/// either side of the program counter by default.
+const DISASSEMBLY_CONTEXT: usize = 4;
+
+/// How many bytes `memory` shows by default.
+const DEFAULT_DUMP_LENGTH: usize = 64;
+
+pub const HELP: &str = "\
+Commands:
+  break [where]          set a breakpoint, or list them (b)
+  delete <where>         remove a breakpoint (d)
+  step [count]           execute instructions, into calls (s)
+  next [count]           execute instructions, over calls (n)
+  continue               run until a breakpoint or the end (c)
+  finish                 run until the current call returns (f)
+  registers              show registers, flags and pointers (r)
+  memory <where> [len]   hex dump memory (x)
+  stack                  show the stack, top first
+  disassemble [count]    show instructions around the pc (dis)
+  restart                load the program again from the start
+  help                   show this (h)
+  quit                   leave the debugger (q)
+
+<where> is a number, in decimal or 0x hex, or a label.
+WAIT instructions don't pause while debugging.
+An empty line repeats the last command.";
+
+
+pub struct Debugger {
+    program: LoadedProgram,
+    runner: RocCPURunner,
+    stepper: Stepper,
+}
+
+impl Debugger {
+    pub fn new(program: LoadedProgram) -> Self {
+        let runner = program.runner();
+        Self { program, runner, stepper: Stepper::default() }
+    }
+
+    /// Runs one command line. Returns false once
+    /// the debugger should quit.
+    pub fn execute(&mut self, line: &str) -> bool {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return true;
+        };
+        let args: Vec<_> = words.collect();
+
+        let result = match command {
+            "break" | "b" => self.break_command(&args),
+            "delete" | "d" => self.delete_command(&args),
+            "step" | "s" => self.resume_command(&args, StopWhen::Stepped),
+            "next" | "n" => self.resume_command(&args, StopWhen::SteppedOver),
+            "continue" | "c" => self.resume_command(&[], StopWhen::Never),
+            "finish" | "f" => self.resume_command(&[], StopWhen::Returned),
+            "registers" | "r" => {
+                self.print_registers();
+                Ok(())
+            },
+            "memory" | "x" => self.memory_command(&args),
+            "stack" => {
+                self.print_stack();
+                Ok(())
+            },
+            "disassemble" | "dis" => self.disassemble_command(&args),
+            "restart" => {
+                self.restart();
+                Ok(())
+            },
+            "help" | "h" => {
+                println!("{}", HELP);
+                Ok(())
+            },
+            "quit" | "q" => return false,
+            _ => Err(format!("Unknown command \"{}\", try \"help\".", command)),
+        };
+
+        if let Err(message) = result {
+            println!("{}", message);
+        }
+        true
+    }
+
+    /// Shows where the program is stopped.
+    pub fn print_location(&self) {
+        self.print_instruction(self.runner.program_counter(), true);
+    }
+
+
+    fn break_command(&mut self, args: &[&str]) -> Result<(), String> {
+        let Some(arg) = args.first() else {
+            let breakpoints: Vec<_> = self.runner.breakpoints().collect();
+            if breakpoints.is_empty() {
+                println!("No breakpoints.");
+            }
+            for pc in breakpoints {
+                println!("  {}", self.describe_pc(pc));
+            }
+            return Ok(());
+        };
+
+        let pc = self.parse_code_location(arg)?;
+        if self.runner.add_breakpoint(pc) {
+            println!("Breakpoint at {}.", self.describe_pc(pc));
+        } else {
+            println!("There is already a breakpoint at {}.", self.describe_pc(pc));
+        }
+        Ok(())
+    }
+
+    fn delete_command(&mut self, args: &[&str]) -> Result<(), String> {
+        let arg = args.first().ok_or("delete needs a breakpoint to remove.")?;
+        let pc = self.parse_code_location(arg)?;
+        if self.runner.remove_breakpoint(pc) {
+            println!("Removed the breakpoint at {}.", self.describe_pc(pc));
+            Ok(())
+        } else {
+            Err(format!("There is no breakpoint at {}.", self.describe_pc(pc)))
+        }
+    }
+
+    fn resume_command(&mut self, args: &[&str], stop_when: StopWhen) -> Result<(), String> {
+        let count = match args.first() {
+            Some(arg) => parse_number(arg).ok_or_else(|| format!("\"{}\" is not a count.", arg))?,
+            None => 1,
+        };
+
+        for _ in 0..count {
+            match self.stepper.resume(&mut self.runner, stop_when, || false) {
+                Stopped::Finished => {},
+                Stopped::Breakpoint => {
+                    println!("Breakpoint at {}.", self.describe_pc(self.runner.program_counter()));
+                    break;
+                },
+                Stopped::Exited => {
+                    println!("The program exited with {}.", self.runner.state().return_value());
+                    return Ok(());
+                },
+                Stopped::Faulted(fault) => {
+                    println!("The program faulted: {}.", fault);
+                    return Ok(());
+                },
+                // Nothing here interrupts a running program.
+                Stopped::Interrupted => break,
+            }
+        }
+
+        self.print_location();
+        Ok(())
+    }
+
+    fn print_registers(&self) {
+        let state = self.runner.state();
+        for register in RocCPURegister::ALL {
+            let value = state.register(register);
+            println!("  {:<6} {:#04x}  {:>3}", register.to_string(), value, value);
+        }
+
+        let flags = state.flags;
+        let flag = |set: bool, name: char| if set { name } else { '-' };
+        println!(
+            "  flags  {}{}{}{}",
+            flag(flags.zero, 'Z'),
+            flag(flags.carry, 'C'),
+            flag(flags.negative, 'N'),
+            flag(flags.overflow, 'V'),
+        );
+        println!("  pc     {}", self.describe_pc(state.program_counter));
+        println!("  sp     {}", state.stack_pointer);
+    }
+
+    fn memory_command(&self, args: &[&str]) -> Result<(), String> {
+        let arg = args.first().ok_or("memory needs an address to start at.")?;
+        let start = self.parse_data_location(arg)?;
+        let length = match args.get(1) {
+            Some(arg) => parse_number(arg).ok_or_else(|| format!("\"{}\" is not a length.", arg))?,
+            None => DEFAULT_DUMP_LENGTH,
+        };
+
+        let memory = self.runner.memory();
+        let end = start.saturating_add(length).min(memory.len());
+        for (row, bytes) in memory[start..end].chunks(16).enumerate() {
+            let hex: Vec<_> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let text: String = bytes.iter()
+                .map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' })
+                .collect();
+            println!("  {:#06x}  {:<47}  {}", start + row * 16, hex.join(" "), text);
+        }
+        Ok(())
+    }
+
+    fn print_stack(&self) {
+        let stack = self.runner.stack();
+        if stack.is_empty() {
+            println!("The stack is empty.");
+        }
+        for (index, value) in stack.iter().enumerate().rev() {
+            println!("  [{:>3}]  {:#04x}  {:>3}", index, value, value);
+        }
+    }
+
+    fn disassemble_command(&self, args: &[&str]) -> Result<(), String> {
+        let context = match args.first() {
+            Some(arg) => parse_number(arg).ok_or_else(|| format!("\"{}\" is not a count.", arg))?,
+            None => DISASSEMBLY_CONTEXT,
+        };
+
+        let pc = self.runner.program_counter();
+        let starts = &self.program.instruction_starts;
+
+        // Instructions can only be found going forwards, so
+        // if the pc isn't somewhere we know about, start there.
+        let pcs: Vec<_> = match starts.iter().position(|start| *start == pc) {
+            Some(index) => {
+                let first = index.saturating_sub(context);
+                let last = (index + context + 1).min(starts.len());
+                starts[first..last].to_vec()
+            },
+            None => {
+                let mut pcs = vec![];
+                let mut next = pc;
+                while let Ok((_, length)) = self.runner.instruction_at(next) {
+                    pcs.push(next);
+                    if pcs.len() > context {
+                        break;
+                    }
+                    next += length;
+                }
+                pcs
+            },
+        };
+
+        for instruction_pc in pcs {
+            self.print_instruction(instruction_pc, instruction_pc == pc);
+        }
+        Ok(())
+    }
+
+    fn restart(&mut self) {
+        let breakpoints: Vec<_> = self.runner.breakpoints().collect();
+        self.runner = self.program.runner();
+        self.stepper = Stepper::default();
+        for pc in breakpoints {
+            self.runner.add_breakpoint(pc);
+        }
+        self.print_location();
+    }
+
+
+    fn print_instruction(&self, pc: usize, current: bool) {
+        let marker = if current { "=>" } else { "  " };
+        let breakpoint = if self.runner.is_breakpoint(pc) { "*" } else { " " };
+        if let Some(label) = self.program.label_at(pc) {
+            println!("      @{}", label);
+        }
+
+        match self.runner.instruction_at(pc) {
+            Ok((instruction, _)) => {
+                let target = instruction.jump_target()
+                    .and_then(|target| self.program.label_at(target as usize))
+                    .map(|label| format!("  (@{})", label))
+                    .unwrap_or_default();
+                println!("{}{} {:#06x}  {}{}", marker, breakpoint, pc, instruction, target);
+            },
+            Err(fault) => println!("{}{} {:#06x}  ({})", marker, breakpoint, pc, fault),
+        }
+    }
+
+    /// `pc`, and the label there if there is one.
+    fn describe_pc(&self, pc: usize) -> String {
+        match self.program.label_at(pc) {
+            Some(label) => format!("{:#06x} (@{})", pc, label),
+            None => format!("{:#06x}", pc),
+        }
+    }
+
+    fn parse_code_location(&self, text: &str) -> Result<usize, String> {
+        let name = text.strip_prefix('@').unwrap_or(text);
+        if let Some(pc) = self.program.code_labels.get(name) {
+            return Ok(*pc);
+        }
+        parse_number(text).ok_or_else(|| format!("\"{}\" is not a number or a code label.", text))
+    }
+
+    fn parse_data_location(&self, text: &str) -> Result<usize, String> {
+        let name = text.strip_prefix('@').unwrap_or(text);
+        if let Some(address) = self.program.data_labels.get(name) {
+            return Ok(*address as usize);
+        }
+        match parse_number(text) {
+            Some(address) if address < self.runner.memory().len() => Ok(address),
+            Some(address) => Err(format!("{:#x} is past the end of memory.", address)),
+            None => Err(format!("\"{}\" is not a number or a data label.", text)),
+        }
+    }
+}
+
+
+/// Parses decimal, or hex with a `0x` prefix.
+pub fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
@@ -0,0 +1,180 @@
+//! An interactive debugger for Roc programs.
+//!
+//! ```text
+//! roc_debug <program.rasm | program.rocbin> [--load <address>]
+//!           [--gdb <host:port> | --gdb-unix <path>]
+//! roc_debug --dap
+//! ```
+//!
+//! Assembly runs from its instruction list, so the program
+//! counter is an instruction index, unless `--load` places
+//! it in memory. Binaries always run from memory.
+//!
+//! With `--gdb` or `--gdb-unix`, there's no prompt. The
+//! program is served to GDB instead, see `RocCPUGdbServer`.
+//!
+//! With `--dap`, an editor talks the Debug Adapter
+//! Protocol over stdin and stdout, and says which
+//! program to launch itself.
+
+mod dap;
+mod debugger;
+mod program;
+mod stepping;
+
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use roc_cpu::RocCPUGdbServer;
+
+use debugger::Debugger;
+use program::LoadedProgram;
+
+const USAGE: &str = "\
+usage: roc_debug <program.rasm | program.rocbin> [--load <address>]
+                 [--gdb <host:port> | --gdb-unix <path>]
+       roc_debug --dap";
+
+/// Where to wait for GDB, instead of reading commands.
+enum GdbListen {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+enum Mode {
+    Prompt(Args),
+    /// The client picks the program.
+    Dap,
+}
+
+struct Args {
+    path: PathBuf,
+    load_address: Option<u16>,
+    gdb: Option<GdbListen>,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Mode::Prompt(args)) => args,
+        Ok(Mode::Dap) => return serve_dap(),
+        Err(message) => {
+            eprintln!("{}", message);
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let program = match LoadedProgram::open(&args.path, args.load_address) {
+        Ok(program) => program,
+        Err(message) => {
+            eprintln!("Could not load {}:\n{}", args.path.display(), message);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match args.gdb {
+        Some(listen) => serve_gdb(&program, listen),
+        None => {
+            println!("Loaded {}. Type \"help\" for a list of commands.", args.path.display());
+            run_prompt(Debugger::new(program));
+            ExitCode::SUCCESS
+        },
+    }
+}
+
+fn run_prompt(mut debugger: Debugger) {
+    debugger.print_location();
+
+    let stdin = std::io::stdin();
+    let mut last_command = String::new();
+    loop {
+        print!("(roc) ");
+        std::io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {},
+        }
+
+        let line = line.trim();
+        if !line.is_empty() {
+            last_command = line.to_string();
+        }
+        if !debugger.execute(&last_command) {
+            break;
+        }
+    }
+}
+
+fn serve_gdb(program: &LoadedProgram, listen: GdbListen) -> ExitCode {
+    let mut runner = program.runner();
+    let mut server = RocCPUGdbServer::new(&mut runner);
+
+    let result = match listen {
+        GdbListen::Tcp(address) => {
+            println!("Waiting for GDB on {}.", address);
+            server.listen_tcp(address.as_str())
+        },
+        #[cfg(unix)]
+        GdbListen::Unix(path) => {
+            println!("Waiting for GDB on {}.", path.display());
+            server.listen_unix(&path)
+        },
+        #[cfg(not(unix))]
+        GdbListen::Unix(_) => {
+            eprintln!("Unix sockets aren't available on this platform.");
+            return ExitCode::FAILURE;
+        },
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("GDB connection failed: {}", err);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+fn serve_dap() -> ExitCode {
+    dap::DapServer::new().serve();
+    ExitCode::SUCCESS
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Mode, String> {
+    let mut path = None;
+    let mut load_address = None;
+    let mut gdb = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--load" => {
+                let address = args.next().ok_or("--load needs an address.")?;
+                let parsed = match address.strip_prefix("0x") {
+                    Some(hex) => u16::from_str_radix(hex, 16),
+                    None => address.parse(),
+                };
+                load_address = Some(parsed.map_err(|_| format!("\"{}\" is not an address.", address))?);
+            },
+            "--gdb" => {
+                let address = args.next().ok_or("--gdb needs a host:port to listen on.")?;
+                gdb = Some(GdbListen::Tcp(address));
+            },
+            "--gdb-unix" => {
+                let socket = args.next().ok_or("--gdb-unix needs a socket path.")?;
+                gdb = Some(GdbListen::Unix(PathBuf::from(socket)));
+            },
+            "--dap" => return Ok(Mode::Dap),
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument \"{}\".", arg)),
+        }
+    }
+
+    Ok(Mode::Prompt(Args {
+        path: path.ok_or("No program given.")?,
+        load_address,
+        gdb,
+    }))
+}
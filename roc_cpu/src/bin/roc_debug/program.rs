@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use roc_cpu::*;
+use roc_cpu::asm::*;
+
+/// A program as it was loaded, so that it can be loaded
+/// again on restart, along with what the debugger knows
+/// about it.
+pub struct LoadedProgram {
+    source: ProgramSource,
+
+    /// The file the program was loaded from.
+    pub path: PathBuf,
+
+    /// Label names (without the `@`) and the program
+    /// counter each one points at.
+    pub code_labels: BTreeMap<String, usize>,
+
+    /// Label names and the memory address each one
+    /// points at.
+    pub data_labels: BTreeMap<String, u16>,
+
+    /// Where every instruction starts, in order, for
+    /// disassembling around the program counter.
+    pub instruction_starts: Vec<usize>,
+
+    /// The source line each instruction came from, by
+    /// where it starts. Binaries don't have any.
+    pub source_lines: BTreeMap<usize, usize>,
+}
+
+enum ProgramSource {
+    Assembly(RocCPUProgram),
+    /// Assembly placed in memory is loaded as a binary.
+    Binary(RocBinary),
+}
+
+impl LoadedProgram {
+    /// Loads a `.rocbin` file, or assembles anything else.
+    /// Assembly runs from its instruction list unless
+    /// `load_address` is given.
+    pub fn open(path: &Path, load_address: Option<u16>) -> Result<Self, String> {
+        let is_binary = path.extension().is_some_and(|extension| extension == "rocbin");
+        if is_binary {
+            let binary = RocBinary::read_file(path).map_err(|err| err.to_string())?;
+            return Ok(Self::from_binary(path, binary));
+        }
+
+        let (program, listing) = assemble_file_with_listing(path, load_address)
+            .map_err(|err| err.to_string())?;
+        let source = match load_address {
+            Some(address) => {
+                let binary = RocBinary::from_program(&program, address).map_err(|err| err.to_string())?;
+                ProgramSource::Binary(binary)
+            },
+            None => ProgramSource::Assembly(program.clone()),
+        };
+        Ok(Self::from_assembly(path, source, &program, &listing, load_address))
+    }
+
+    fn from_binary(path: &Path, binary: RocBinary) -> Self {
+        let mut loaded = Self {
+            source: ProgramSource::Binary(binary.clone()),
+            path: path.to_path_buf(),
+            code_labels: BTreeMap::new(),
+            data_labels: BTreeMap::new(),
+            instruction_starts: vec![],
+            source_lines: BTreeMap::new(),
+        };
+
+        for symbol in &binary.symbols {
+            loaded.code_labels.insert(symbol.name.clone(), symbol.address as usize);
+            loaded.data_labels.insert(symbol.name.clone(), symbol.address);
+        }
+
+        // Stops at the first byte that isn't an instruction,
+        // which leaves data after the code undisassembled.
+        let mut runner = RocCPURunner::default();
+        runner.load_binary(&binary);
+        let start = binary.code.load_address as usize;
+        let mut pc = start;
+        while pc < start + binary.code.bytes.len() {
+            match runner.instruction_at(pc) {
+                Ok((_, length)) => {
+                    loaded.instruction_starts.push(pc);
+                    pc += length;
+                },
+                Err(_) => break,
+            }
+        }
+
+        loaded
+    }
+
+    fn from_assembly(
+        path: &Path,
+        source: ProgramSource,
+        program: &RocCPUProgram,
+        listing: &RocAsmListing,
+        load_address: Option<u16>
+    ) -> Self {
+        let mut loaded = Self {
+            source,
+            path: path.to_path_buf(),
+            code_labels: BTreeMap::new(),
+            data_labels: BTreeMap::new(),
+            instruction_starts: vec![],
+            source_lines: BTreeMap::new(),
+        };
+
+        // Code comes first in a listing, one line for
+        // each instruction.
+        for (index, line) in listing.lines.iter().enumerate() {
+            let location = match line.location {
+                RocAsmListingLocation::Index(index) => index,
+                RocAsmListingLocation::Address(address) => address as usize,
+            };
+            if index < program.len() {
+                loaded.instruction_starts.push(location);
+                loaded.source_lines.insert(location, line.line);
+            }
+
+            for label in &line.labels {
+                // Anonymous labels can't be named.
+                let Some(name) = label.strip_prefix('@').filter(|name| !name.starts_with('@')) else {
+                    continue;
+                };
+
+                match line.location {
+                    RocAsmListingLocation::Index(index) => {
+                        loaded.code_labels.insert(name.to_string(), index);
+                    },
+                    // Without a load address, only data has one,
+                    // but with one, code and data share memory.
+                    RocAsmListingLocation::Address(address) => {
+                        if load_address.is_some() {
+                            loaded.code_labels.insert(name.to_string(), address as usize);
+                        }
+                        loaded.data_labels.insert(name.to_string(), address);
+                    },
+                }
+            }
+        }
+
+        loaded
+    }
+
+    /// A fresh runner with the program loaded.
+    pub fn runner(&self) -> RocCPURunner {
+        let mut runner = RocCPURunner::default();
+        match &self.source {
+            ProgramSource::Assembly(program) => runner.load_program(program),
+            ProgramSource::Binary(binary) => runner.load_binary(binary),
+        }
+        runner
+    }
+
+    /// The name of the label at `pc`, if there is one.
+    pub fn label_at(&self, pc: usize) -> Option<&str> {
+        self.code_labels.iter()
+            .find(|(_, location)| **location == pc)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+// Only the DAP server works in source lines and routines.
+impl LoadedProgram {
+    /// Where the first instruction on `line`, or failing
+    /// that the next line with one, starts, along with
+    /// the line it's on.
+    pub fn instruction_on_line(&self, line: usize) -> Option<(usize, usize)> {
+        self.source_lines.iter()
+            .filter(|(_, source_line)| **source_line >= line)
+            .min_by_key(|(pc, source_line)| (**source_line, **pc))
+            .map(|(pc, source_line)| (*pc, *source_line))
+    }
+
+    /// The nearest named label at or before `pc`, which is
+    /// usually the routine it's in.
+    pub fn routine_at(&self, pc: usize) -> Option<&str> {
+        self.code_labels.iter()
+            .filter(|(_, location)| **location <= pc)
+            .max_by_key(|(_, location)| **location)
+            .map(|(name, _)| name.as_str())
+    }
+}
@@ -0,0 +1,174 @@
+use roc_cpu::*;
+
+/// How many instructions run between checks
+/// for the user asking to stop.
+const STEPS_BETWEEN_INTERRUPT_CHECKS: usize = 1024;
+
+/// When a resumed program should stop, besides
+/// at a breakpoint, exit or fault.
+#[derive(Clone, Copy)]
+pub enum StopWhen {
+    /// After one instruction.
+    Stepped,
+    /// After one instruction, or if that was a `CALL`,
+    /// once the call returns.
+    SteppedOver,
+    /// Once the current call returns.
+    Returned,
+    /// Only at a breakpoint.
+    Never,
+}
+
+/// Why a resumed program stopped.
+pub enum Stopped {
+    Breakpoint,
+    Finished,
+    Exited,
+    Faulted(RocCPUFault),
+    Interrupted,
+}
+
+/// Resumes a program, keeping track of which calls
+/// it's in along the way.
+#[derive(Default)]
+pub struct Stepper {
+    /// Where each `CALL` that hasn't returned yet
+    /// was made from, outermost first.
+    pub calls: Vec<usize>,
+}
+
+impl Stepper {
+    /// Executes at least one instruction, then carries on
+    /// until `stop_when` says to, a breakpoint is hit, or
+    /// `interrupted` returns true. `Wait` doesn't pause.
+    pub fn resume(
+        &mut self,
+        runner: &mut RocCPURunner,
+        stop_when: StopWhen,
+        mut interrupted: impl FnMut() -> bool
+    ) -> Stopped {
+        // How many calls deep we are from where we started.
+        let mut depth: isize = 0;
+        let mut steps = 0;
+
+        loop {
+            if steps > 0 && runner.is_breakpoint(runner.program_counter()) {
+                return Stopped::Breakpoint;
+            }
+            if steps > 0 && steps % STEPS_BETWEEN_INTERRUPT_CHECKS == 0 && interrupted() {
+                return Stopped::Interrupted;
+            }
+
+            let pc = runner.program_counter();
+            let instruction = runner.current_instruction().ok().map(|(instruction, _)| instruction);
+            match runner.step() {
+                RocCPUStepOutcome::Continued | RocCPUStepOutcome::Waiting(_) => {},
+                RocCPUStepOutcome::Exited => return Stopped::Exited,
+                RocCPUStepOutcome::Faulted(fault) => return Stopped::Faulted(fault),
+            }
+            steps += 1;
+
+            match instruction {
+                Some(RocCPUInstruction::Call(..)) => {
+                    self.calls.push(pc);
+                    depth += 1;
+                },
+                Some(RocCPUInstruction::Return) => {
+                    self.calls.pop();
+                    depth -= 1;
+                },
+                _ => {},
+            }
+
+            let done = match stop_when {
+                StopWhen::Stepped => true,
+                StopWhen::SteppedOver => depth <= 0,
+                StopWhen::Returned => depth < 0,
+                StopWhen::Never => false,
+            };
+            if done {
+                return Stopped::Finished;
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Calls `@outer`, which calls `@inner`, one
+    /// instruction index for each line.
+    const NESTED: &str = "
+        CALL @outer;
+        EXIT;
+        @outer CALL @inner;
+        RETURN;
+        @inner NOP;
+        RETURN;
+    ";
+
+    fn runner(source: &str) -> RocCPURunner {
+        let mut runner = RocCPURunner::default();
+        runner.load_program(&asm::assemble(source).unwrap());
+        runner
+    }
+
+    fn never_interrupted() -> bool {
+        false
+    }
+
+    #[test]
+    fn stepping_goes_into_calls() {
+        let mut runner = runner(NESTED);
+        let mut stepper = Stepper::default();
+        assert!(matches!(stepper.resume(&mut runner, StopWhen::Stepped, never_interrupted), Stopped::Finished));
+        assert_eq!((runner.program_counter(), stepper.calls.as_slice()), (2, [0].as_slice()));
+    }
+
+    #[test]
+    fn stepping_over_a_call_runs_all_of_it() {
+        let mut runner = runner(NESTED);
+        let mut stepper = Stepper::default();
+        assert!(matches!(stepper.resume(&mut runner, StopWhen::SteppedOver, never_interrupted), Stopped::Finished));
+        assert_eq!(runner.program_counter(), 1);
+        assert!(stepper.calls.is_empty());
+    }
+
+    #[test]
+    fn returning_leaves_only_the_current_call() {
+        let mut runner = runner(NESTED);
+        let mut stepper = Stepper::default();
+        stepper.resume(&mut runner, StopWhen::Stepped, never_interrupted);
+        stepper.resume(&mut runner, StopWhen::Stepped, never_interrupted);
+        assert_eq!((runner.program_counter(), stepper.calls.as_slice()), (4, [0, 2].as_slice()));
+
+        assert!(matches!(stepper.resume(&mut runner, StopWhen::Returned, never_interrupted), Stopped::Finished));
+        assert_eq!((runner.program_counter(), stepper.calls.as_slice()), (3, [0].as_slice()));
+    }
+
+    #[test]
+    fn breakpoints_stop_a_call_being_stepped_over() {
+        let mut runner = runner(NESTED);
+        runner.add_breakpoint(4);
+        let mut stepper = Stepper::default();
+        assert!(matches!(stepper.resume(&mut runner, StopWhen::SteppedOver, never_interrupted), Stopped::Breakpoint));
+        assert_eq!((runner.program_counter(), stepper.calls.as_slice()), (4, [0, 2].as_slice()));
+    }
+
+    #[test]
+    fn breakpoints_where_it_starts_are_stepped_past() {
+        let mut runner = runner(NESTED);
+        runner.add_breakpoint(0);
+        let mut stepper = Stepper::default();
+        assert!(matches!(stepper.resume(&mut runner, StopWhen::Never, never_interrupted), Stopped::Exited));
+    }
+
+    #[test]
+    fn endless_loops_can_be_interrupted() {
+        let mut runner = runner("@top JUMP @top;");
+        let mut stepper = Stepper::default();
+        assert!(matches!(stepper.resume(&mut runner, StopWhen::Never, || true), Stopped::Interrupted));
+    }
+}
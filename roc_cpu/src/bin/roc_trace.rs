@@ -0,0 +1,272 @@
+//! Records execution traces, and finds where two
+//! of them stop agreeing.
+//!
+//! ```text
+//! roc_trace record <program.rasm | program.rocbin> <trace>
+//!                  [--load <address>] [--max-steps <count>]
+//! roc_trace diff <left trace> <right trace>
+//! ```
+//!
+//! `record` writes a binary trace if the trace's name ends
+//! in `.roctrace`, and JSON Lines otherwise. `WAIT`
+//! instructions don't pause while recording.
+//!
+//! `diff` reads either format, and exits with 1 if the
+//! traces differ, or 2 if they couldn't be read.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use roc_cpu::*;
+
+const USAGE: &str = "\
+usage: roc_trace record <program.rasm | program.rocbin> <trace>
+                        [--load <address>] [--max-steps <count>]
+       roc_trace diff <left trace> <right trace>";
+
+/// Why a command didn't finish.
+enum Failure {
+    /// The arguments didn't make sense.
+    Usage(String),
+    Error(String),
+}
+
+impl From<&str> for Failure {
+    fn from(message: &str) -> Self {
+        Self::Usage(message.to_string())
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Self::Usage(message)
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("record") => record(&args[1..]),
+        Some("diff") => diff(&args[1..]),
+        _ => Err(Failure::from("Expected \"record\" or \"diff\".")),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(Failure::Usage(message)) => {
+            eprintln!("{}", message);
+            eprintln!("{}", USAGE);
+            ExitCode::from(2)
+        },
+        Err(Failure::Error(message)) => {
+            eprintln!("{}", message);
+            ExitCode::from(2)
+        },
+    }
+}
+
+fn record(args: &[String]) -> Result<ExitCode, Failure> {
+    let mut paths = vec![];
+    let mut load_address = None;
+    let mut max_steps = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--load" => {
+                let address = args.next().ok_or("--load needs an address.")?;
+                let address = parse_number(address)
+                    .and_then(|address| u16::try_from(address).ok())
+                    .ok_or_else(|| format!("\"{}\" is not an address.", address))?;
+                load_address = Some(address);
+            },
+            "--max-steps" => {
+                let count = args.next().ok_or("--max-steps needs a count.")?;
+                max_steps = Some(parse_number(count).ok_or_else(|| format!("\"{}\" is not a count.", count))?);
+            },
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let [program_path, trace_path] = paths.as_slice() else {
+        return Err(Failure::from("record needs a program and somewhere to write its trace."));
+    };
+
+    let mut runner = load(program_path, load_address)
+        .map_err(|message| Failure::Error(format!("Could not load {}:\n{}", program_path.display(), message)))?;
+
+    let file = File::create(trace_path)
+        .map_err(|err| Failure::Error(format!("Could not create {}: {}", trace_path.display(), err)))?;
+    let writer = BufWriter::new(file);
+    let is_binary = trace_path.extension().is_some_and(|extension| extension == "roctrace");
+    if is_binary {
+        let tracer = RocCPUBinaryTracer::new(writer)
+            .map_err(|err| Failure::Error(format!("Could not write {}: {}", trace_path.display(), err)))?;
+        runner.set_tracer(Box::new(tracer));
+    } else {
+        runner.set_tracer(Box::new(RocCPUJsonLinesTracer::new(writer)));
+    }
+
+    let mut steps = 0;
+    let outcome = loop {
+        if max_steps.is_some_and(|max_steps| steps == max_steps) {
+            break None;
+        }
+        match runner.step() {
+            RocCPUStepOutcome::Continued | RocCPUStepOutcome::Waiting(_) => steps += 1,
+            outcome => break Some(outcome),
+        }
+    };
+
+    runner.stop_tracing()
+        .map_err(|err| Failure::Error(format!("Could not write {}: {}", trace_path.display(), err)))?;
+
+    match outcome {
+        Some(RocCPUStepOutcome::Faulted(fault)) => println!("The program faulted: {}.", fault),
+        Some(_) => println!("The program exited with {}.", runner.state().return_value()),
+        None => println!("Stopped after {} steps.", steps),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn diff(args: &[String]) -> Result<ExitCode, Failure> {
+    let [left_path, right_path] = args else {
+        return Err(Failure::from("diff needs two traces."));
+    };
+    let read = |path: &String| {
+        RocCPUTrace::read_file(path)
+            .map_err(|err| Failure::Error(format!("Could not read {}: {}", path, err)))
+    };
+    let (left, right) = (read(left_path)?, read(right_path)?);
+
+    let Some(divergence) = left.first_divergence(&right) else {
+        println!("The traces are the same, {} instructions long.", left.entries.len());
+        return Ok(ExitCode::SUCCESS);
+    };
+
+    let index = match divergence {
+        RocCPUTraceDivergence::Differs { index } => index,
+        RocCPUTraceDivergence::LeftEnded { index } => index,
+        RocCPUTraceDivergence::RightEnded { index } => index,
+    };
+    println!("The traces agree for {} instructions.", index);
+    if let Some(previous) = index.checked_sub(1).map(|previous| &left.entries[previous]) {
+        println!("  last shared: {}", previous);
+    }
+
+    match divergence {
+        RocCPUTraceDivergence::Differs { index } => {
+            let (left_entry, right_entry) = (&left.entries[index], &right.entries[index]);
+            println!("Then they differ in {}:", differences(left_entry, right_entry).join(", "));
+            println!("  left:  {}", left_entry);
+            println!("  right: {}", right_entry);
+        },
+        RocCPUTraceDivergence::LeftEnded { index } => {
+            println!("Then the left trace ends, but the right one carries on:");
+            println!("  right: {}", right.entries[index]);
+        },
+        RocCPUTraceDivergence::RightEnded { index } => {
+            println!("Then the right trace ends, but the left one carries on:");
+            println!("  left:  {}", left.entries[index]);
+        },
+    }
+    Ok(ExitCode::FAILURE)
+}
+
+/// What two entries disagree about.
+fn differences(left: &RocCPUTraceEntry, right: &RocCPUTraceEntry) -> Vec<&'static str> {
+    let mut differences = vec![];
+    if left.pc != right.pc {
+        differences.push("pc");
+    }
+    if left.instruction != right.instruction {
+        differences.push("instruction");
+    }
+    if left.registers != right.registers {
+        differences.push("registers");
+    }
+    if left.flags != right.flags {
+        differences.push("flags");
+    }
+    if left.memory != right.memory {
+        differences.push("memory");
+    }
+    if left.stack != right.stack {
+        differences.push("stack");
+    }
+    differences
+}
+
+/// Loads a `.rocbin` file, or assembles anything else.
+fn load(path: &Path, load_address: Option<u16>) -> Result<RocCPURunner, String> {
+    let mut runner = RocCPURunner::default();
+    let is_binary = path.extension().is_some_and(|extension| extension == "rocbin");
+    if is_binary {
+        runner.load_binary_file(path).map_err(|err| err.to_string())?;
+        return Ok(runner);
+    }
+
+    let program = asm::assemble_file(path).map_err(|err| err.to_string())?;
+    match load_address {
+        Some(address) => {
+            runner.load_program_into_memory(&program, address).map_err(|err| err.to_string())?;
+        },
+        None => runner.load_program(&program),
+    }
+    Ok(runner)
+}
+
+/// Parses decimal, or hex with a `0x` prefix.
+fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records `source` into a trace file named `trace_name`
+    /// and reads it back.
+    fn record_and_read(source: &str, trace_name: &str) -> RocCPUTrace {
+        let directory = std::env::temp_dir().join(format!("roc_trace_test_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let program_path = directory.join("program.rasm");
+        let trace_path = directory.join(trace_name);
+        std::fs::write(&program_path, source).unwrap();
+
+        let args = [program_path.display().to_string(), trace_path.display().to_string()];
+        assert!(matches!(record(&args), Ok(code) if code == ExitCode::SUCCESS));
+        RocCPUTrace::read_file(&trace_path).unwrap()
+    }
+
+    #[test]
+    fn both_formats_record_the_same_trace() {
+        let source = "PUT $ax, 3; PUSH $ax; POP $bx; STORE 0x4000, $bx; EXIT;";
+        let binary = record_and_read(source, "both.roctrace");
+        let json = record_and_read(source, "both.jsonl");
+        assert_eq!(binary.entries.len(), 5);
+        assert_eq!(binary, json);
+    }
+
+    #[test]
+    fn differences_name_what_disagrees() {
+        let trace = record_and_read("PUT $ax, 3; PUT $bx, 4; EXIT;", "differences.roctrace");
+        let (left, right) = (&trace.entries[0], &trace.entries[1]);
+        assert_eq!(differences(left, left), Vec::<&str>::new());
+        assert_eq!(differences(left, right), ["pc", "instruction", "registers"]);
+    }
+
+    #[test]
+    fn numbers_are_decimal_or_hex() {
+        assert_eq!(parse_number("16"), Some(16));
+        assert_eq!(parse_number("0x10"), Some(16));
+        assert_eq!(parse_number("0X1f"), Some(31));
+        assert_eq!(parse_number("0x"), None);
+        assert_eq!(parse_number("ten"), None);
+    }
+}
@@ -0,0 +1,447 @@
+//! The `.rocbin` program container.
+//!
+//! Every multi-byte number is big-endian, matching the
+//! `hi, lo` order addresses are written in elsewhere.
+//!
+//! ```text
+//! magic           4 bytes   "RocB"
+//! version         u16       ROCBIN_VERSION
+//! entry point     u16       byte address execution starts at
+//! flags           u16       bit 0: a symbol table follows the sections
+//! section count   u16
+//! sections, each:
+//!     kind        u8        0 = code, 1 = data
+//!     address     u16       where the section is loaded
+//!     length      u32
+//!     bytes       `length` bytes
+//! symbol table (optional):
+//!     count       u16
+//!     symbols, each:
+//!         address u16
+//!         length  u8
+//!         name    `length` bytes of UTF-8
+//! ```
+//!
+//! There is exactly one code section. Code is encoded
+//! with `ProgramEncodable`, and its jump and call targets
+//! are byte addresses, see `encode_program`.
+
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use roc_cpu_traits::ProgramDecodeError;
+use roc_cpu_types::*;
+
+pub const ROCBIN_MAGIC: [u8; 4] = *b"RocB";
+pub const ROCBIN_VERSION: u16 = 1;
+
+const FLAG_HAS_SYMBOLS: u16 = 0x1;
+
+const SECTION_KIND_CODE: u8 = 0;
+const SECTION_KIND_DATA: u8 = 1;
+
+const MEMORY_SIZE: usize = 0x10000;
+
+
+/// Some bytes, and the address they are loaded at.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RocBinarySection {
+    pub load_address: u16,
+    pub bytes: Vec<u8>,
+}
+
+/// A name for an address, kept for debugging.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RocBinarySymbol {
+    pub name: String,
+    pub address: u16,
+}
+
+/// A program, ready to be loaded into memory by
+/// `RocCPURunner::load_binary`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RocBinary {
+    pub entry_point: u16,
+    pub code: RocBinarySection,
+    pub data: Vec<RocBinarySection>,
+    pub symbols: Vec<RocBinarySymbol>,
+}
+
+#[derive(Debug)]
+pub enum RocBinaryError {
+    Io(std::io::Error),
+
+    /// The file doesn't start with `ROCBIN_MAGIC`.
+    BadMagic,
+
+    /// The file was written by a newer version
+    /// of the format.
+    UnsupportedVersion(u16),
+
+    /// The file ends part way through.
+    Truncated,
+
+    /// The file has no code section, or more than one.
+    BadCodeSection,
+
+    /// A section has a kind other than code or data.
+    UnknownSectionKind(u8),
+
+    /// A section doesn't fit in memory at its load address.
+    SectionOutOfRange { load_address: u16, length: usize },
+
+    /// Two sections, by load address, share some memory,
+    /// so loading one would overwrite the other.
+    SectionsOverlap { first: u16, second: u16 },
+
+    /// A symbol name isn't valid UTF-8, or is too long
+    /// to be written.
+    BadSymbolName,
+
+    /// There are more sections, code included, than
+    /// the format can count.
+    TooManySections(usize),
+
+    /// There are more symbols than the format can count.
+    TooManySymbols(usize),
+
+    /// There was nothing loaded to write out.
+    NoProgram,
+}
+
+impl fmt::Display for RocBinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::BadMagic => write!(f, "not a .rocbin file"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported .rocbin version {}", version)
+            },
+            Self::Truncated => write!(f, "file ends unexpectedly"),
+            Self::BadCodeSection => write!(f, "expected exactly one code section"),
+            Self::UnknownSectionKind(kind) => write!(f, "unknown section kind {}", kind),
+            Self::SectionOutOfRange { load_address, length } => {
+                write!(f, "{} byte section at {:#06x} does not fit in memory", length, load_address)
+            },
+            Self::SectionsOverlap { first, second } => {
+                write!(f, "sections at {:#06x} and {:#06x} overlap", first, second)
+            },
+            Self::BadSymbolName => write!(f, "invalid symbol name"),
+            Self::TooManySections(count) => write!(f, "{} sections is too many to write", count),
+            Self::TooManySymbols(count) => write!(f, "{} symbols is too many to write", count),
+            Self::NoProgram => write!(f, "no program is loaded"),
+        }
+    }
+}
+
+impl std::error::Error for RocBinaryError {}
+
+impl From<std::io::Error> for RocBinaryError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+
+impl RocBinary {
+    /// Encodes `program` as a code section loaded (and
+    /// entered) at `load_address`, with a data section
+    /// for each of its data segments.
+    ///
+    /// A program doesn't keep its labels, so the binary
+    /// has no symbols. Only binaries built by hand carry
+    /// them.
+    pub fn from_program(program: &RocCPUProgram, load_address: u16) -> Result<Self, RocBinaryError> {
+        let mut binary = Self::from_image(encode_program(program, load_address), load_address);
+        binary.data = program.data.iter()
+            .map(|segment| RocBinarySection {
+                load_address: segment.address,
+                bytes: segment.bytes.clone(),
+            })
+            .collect();
+        binary.check_overlaps()?;
+        Ok(binary)
+    }
+
+    /// Wraps already-encoded code, loaded (and entered)
+    /// at `load_address`.
+    pub fn from_image(image: Vec<u8>, load_address: u16) -> Self {
+        Self {
+            entry_point: load_address,
+            code: RocBinarySection { load_address, bytes: image },
+            data: vec![],
+            symbols: vec![],
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, RocBinaryError> {
+        let mut output = vec![];
+        output.extend_from_slice(&ROCBIN_MAGIC);
+        output.extend_from_slice(&ROCBIN_VERSION.to_be_bytes());
+        output.extend_from_slice(&self.entry_point.to_be_bytes());
+
+        let flags = if self.symbols.is_empty() { 0 } else { FLAG_HAS_SYMBOLS };
+        output.extend_from_slice(&flags.to_be_bytes());
+
+        let section_count = self.data.len() + 1;
+        let section_count = u16::try_from(section_count)
+            .map_err(|_| RocBinaryError::TooManySections(section_count))?;
+        output.extend_from_slice(&section_count.to_be_bytes());
+
+        write_section(&mut output, SECTION_KIND_CODE, &self.code)?;
+        for section in &self.data {
+            write_section(&mut output, SECTION_KIND_DATA, section)?;
+        }
+
+        if !self.symbols.is_empty() {
+            let symbol_count = u16::try_from(self.symbols.len())
+                .map_err(|_| RocBinaryError::TooManySymbols(self.symbols.len()))?;
+            output.extend_from_slice(&symbol_count.to_be_bytes());
+            for symbol in &self.symbols {
+                let name = symbol.name.as_bytes();
+                if name.len() > u8::MAX as usize {
+                    return Err(RocBinaryError::BadSymbolName);
+                }
+
+                output.extend_from_slice(&symbol.address.to_be_bytes());
+                output.push(name.len() as u8);
+                output.extend_from_slice(name);
+            }
+        }
+
+        Ok(output)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RocBinaryError> {
+        let mut reader = ByteReader { bytes, position: 0 };
+
+        if reader.take(4)? != ROCBIN_MAGIC {
+            return Err(RocBinaryError::BadMagic);
+        }
+
+        let version = reader.u16()?;
+        if version != ROCBIN_VERSION {
+            return Err(RocBinaryError::UnsupportedVersion(version));
+        }
+
+        let entry_point = reader.u16()?;
+        let flags = reader.u16()?;
+        let section_count = reader.u16()?;
+
+        let mut code = None;
+        let mut data = vec![];
+        for _ in 0..section_count {
+            let kind = reader.u8()?;
+            let load_address = reader.u16()?;
+            let length = reader.u32()? as usize;
+            if load_address as usize + length > MEMORY_SIZE {
+                return Err(RocBinaryError::SectionOutOfRange { load_address, length });
+            }
+
+            let section = RocBinarySection {
+                load_address,
+                bytes: reader.take(length)?.to_vec(),
+            };
+            match kind {
+                SECTION_KIND_CODE if code.is_none() => code = Some(section),
+                SECTION_KIND_CODE => return Err(RocBinaryError::BadCodeSection),
+                SECTION_KIND_DATA => data.push(section),
+                _ => return Err(RocBinaryError::UnknownSectionKind(kind)),
+            }
+        }
+
+        let mut symbols = vec![];
+        if flags & FLAG_HAS_SYMBOLS != 0 {
+            let symbol_count = reader.u16()?;
+            for _ in 0..symbol_count {
+                let address = reader.u16()?;
+                let length = reader.u8()? as usize;
+                let name = std::str::from_utf8(reader.take(length)?)
+                    .map_err(|_| RocBinaryError::BadSymbolName)?;
+                symbols.push(RocBinarySymbol { name: name.to_string(), address });
+            }
+        }
+
+        let binary = Self {
+            entry_point,
+            code: code.ok_or(RocBinaryError::BadCodeSection)?,
+            data,
+            symbols,
+        };
+        binary.check_overlaps()?;
+        Ok(binary)
+    }
+
+    /// Checks that no two sections share memory, since
+    /// `RocCPURunner::load_binary` would silently let the
+    /// code overwrite the data.
+    fn check_overlaps(&self) -> Result<(), RocBinaryError> {
+        let mut sections: Vec<_> = std::iter::once(&self.code)
+            .chain(&self.data)
+            .filter(|section| !section.bytes.is_empty())
+            .collect();
+        sections.sort_by_key(|section| section.load_address);
+
+        for pair in sections.windows(2) {
+            let end = pair[0].load_address as usize + pair[0].bytes.len();
+            if end > pair[1].load_address as usize {
+                return Err(RocBinaryError::SectionsOverlap {
+                    first: pair[0].load_address,
+                    second: pair[1].load_address,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), RocBinaryError> {
+        writer.write_all(&self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, RocBinaryError> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<(), RocBinaryError> {
+        std::fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Self, RocBinaryError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// The code section as Roc assembly, with jump and call
+    /// targets named from the symbol table where possible.
+    /// Data sections are left out.
+    pub fn disassemble(&self) -> Result<String, ProgramDecodeError> {
+        let symbols = self.symbols.iter()
+            .map(|symbol| (symbol.address, symbol.name.clone()))
+            .collect();
+        roc_cpu_asm::disassemble_image(&self.code.bytes, self.code.load_address, &symbols)
+    }
+
+    /// The name of the symbol at `address`, if any.
+    pub fn symbol_at(&self, address: u16) -> Option<&str> {
+        self.symbols.iter()
+            .find(|s| s.address == address)
+            .map(|s| s.name.as_str())
+    }
+}
+
+
+fn write_section(output: &mut Vec<u8>, kind: u8, section: &RocBinarySection) -> Result<(), RocBinaryError> {
+    let length = section.bytes.len();
+    if section.load_address as usize + length > MEMORY_SIZE {
+        return Err(RocBinaryError::SectionOutOfRange {
+            load_address: section.load_address,
+            length,
+        });
+    }
+
+    output.push(kind);
+    output.extend_from_slice(&section.load_address.to_be_bytes());
+    output.extend_from_slice(&(length as u32).to_be_bytes());
+    output.extend_from_slice(&section.bytes);
+    Ok(())
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], RocBinaryError> {
+        let end = self.position.checked_add(count).ok_or(RocBinaryError::Truncated)?;
+        let taken = self.bytes.get(self.position..end).ok_or(RocBinaryError::Truncated)?;
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, RocBinaryError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, RocBinaryError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, RocBinaryError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binaries_round_trip() {
+        let mut binary = RocBinary::from_image(vec![0x80], 0x100);
+        binary.data.push(RocBinarySection { load_address: 0x4000, bytes: vec![1, 2, 3] });
+        binary.symbols.push(RocBinarySymbol { name: "start".to_string(), address: 0x100 });
+
+        let bytes = binary.to_bytes().unwrap();
+        assert_eq!(RocBinary::from_bytes(&bytes).unwrap(), binary);
+    }
+
+    #[test]
+    fn images_with_data_disassemble() {
+        let image = roc_cpu_asm::assemble_to_image("@value .byte 7; LOAD $ax, @value; EXIT;", 0x100).unwrap();
+        let text = RocBinary::from_image(image.clone(), 0x100).disassemble().unwrap();
+        assert_eq!(roc_cpu_asm::assemble_to_image(&text, 0x100).unwrap(), image, "{}", text);
+    }
+
+    #[test]
+    fn programs_keep_their_data() {
+        let program = roc_cpu_asm::assemble("@value .byte 7; LOAD $ax, @value; EXIT;").unwrap();
+        let binary = RocBinary::from_program(&program, 0x100).unwrap();
+        assert_eq!(binary.data, [RocBinarySection { load_address: 0x4000, bytes: vec![7] }]);
+        assert!(binary.symbols.is_empty());
+    }
+
+    #[test]
+    fn code_overlapping_data_is_an_error() {
+        let program = roc_cpu_asm::assemble("@value .byte 7; LOAD $ax, @value; EXIT;").unwrap();
+        assert!(matches!(
+            RocBinary::from_program(&program, 0x3FFF),
+            Err(RocBinaryError::SectionsOverlap { first: 0x3FFF, second: 0x4000 }),
+        ));
+    }
+
+    #[test]
+    fn overlapping_sections_are_read_as_errors() {
+        let mut binary = RocBinary::from_image(vec![0x80; 4], 0x100);
+        binary.data.push(RocBinarySection { load_address: 0x200, bytes: vec![1, 2] });
+        binary.data.push(RocBinarySection { load_address: 0x201, bytes: vec![3] });
+
+        let bytes = binary.to_bytes().unwrap();
+        assert!(matches!(
+            RocBinary::from_bytes(&bytes),
+            Err(RocBinaryError::SectionsOverlap { first: 0x200, second: 0x201 }),
+        ));
+    }
+
+    #[test]
+    fn too_many_sections_are_errors() {
+        let mut binary = RocBinary::from_image(vec![], 0);
+        binary.data = vec![RocBinarySection::default(); u16::MAX as usize];
+        assert!(matches!(binary.to_bytes(), Err(RocBinaryError::TooManySections(0x10000))));
+    }
+
+    #[test]
+    fn too_many_symbols_are_errors() {
+        let mut binary = RocBinary::from_image(vec![], 0);
+        let symbol = RocBinarySymbol { name: "a".to_string(), address: 0 };
+        binary.symbols = vec![symbol; u16::MAX as usize + 1];
+        assert!(matches!(binary.to_bytes(), Err(RocBinaryError::TooManySymbols(0x10000))));
+    }
+}
@@ -0,0 +1,153 @@
+use std::io::{self, Read, Write};
+
+/// The byte GDB sends, outside of any packet,
+/// to interrupt a running program.
+const INTERRUPT: u8 = 0x03;
+
+/// A stream GDB is connected over. Being able to stop
+/// blocking lets a running program be interrupted.
+pub(super) trait RocCPUGdbStream: Read + Write {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+}
+
+impl RocCPUGdbStream for std::net::TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        std::net::TcpStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+#[cfg(unix)]
+impl RocCPUGdbStream for std::os::unix::net::UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        std::os::unix::net::UnixStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+
+/// What came in from GDB.
+pub(super) enum Received {
+    /// The body of a `$...#xx` packet.
+    Packet(Vec<u8>),
+    Interrupt,
+}
+
+/// Reads and writes `$body#checksum` packets.
+pub(super) struct Connection<S: RocCPUGdbStream> {
+    stream: S,
+
+    /// Bytes read but not yet used.
+    pending: Vec<u8>,
+
+    /// Set once GDB asks for `QStartNoAckMode`, after
+    /// which packets are no longer acknowledged.
+    pub no_ack: bool,
+}
+
+impl<S: RocCPUGdbStream> Connection<S> {
+    pub fn new(stream: S) -> Self {
+        Self { stream, pending: vec![], no_ack: false }
+    }
+
+    /// Waits for the next packet or interrupt. Returns
+    /// `None` once GDB hangs up.
+    pub fn receive(&mut self) -> io::Result<Option<Received>> {
+        loop {
+            if let Some(received) = self.take_received()? {
+                return Ok(Some(received));
+            }
+            if !self.fill()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Whether GDB has asked to interrupt the program,
+    /// without waiting. Anything else that arrived is
+    /// kept for `receive`.
+    pub fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let filled = self.fill();
+        self.stream.set_nonblocking(false)?;
+
+        match filled {
+            Ok(_) => {},
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {},
+            Err(err) => return Err(err),
+        }
+
+        match self.pending.iter().position(|byte| *byte == INTERRUPT) {
+            Some(index) => {
+                self.pending.remove(index);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    pub fn send(&mut self, body: &[u8]) -> io::Result<()> {
+        let checksum = body.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        let mut packet = Vec::with_capacity(body.len() + 4);
+        packet.push(b'$');
+        packet.extend(body);
+        packet.extend(format!("#{:02x}", checksum).bytes());
+
+        self.stream.write_all(&packet)?;
+        self.stream.flush()
+    }
+
+    /// Reads whatever is available. Returns false
+    /// once the stream has closed.
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut buffer = [0; 4096];
+        let read = self.stream.read(&mut buffer)?;
+        self.pending.extend_from_slice(&buffer[..read]);
+        Ok(read > 0)
+    }
+
+    /// Takes the first whole packet or interrupt out of
+    /// `pending`, acknowledging packets as it goes.
+    fn take_received(&mut self) -> io::Result<Option<Received>> {
+        loop {
+            let Some(&first) = self.pending.first() else {
+                return Ok(None);
+            };
+
+            match first {
+                INTERRUPT => {
+                    self.pending.remove(0);
+                    return Ok(Some(Received::Interrupt));
+                },
+                b'$' => {},
+                // Acknowledgements of our packets, and
+                // anything else between packets.
+                _ => {
+                    self.pending.remove(0);
+                    continue;
+                },
+            }
+
+            let Some(end) = self.pending.iter().position(|byte| *byte == b'#') else {
+                return Ok(None);
+            };
+            if self.pending.len() < end + 3 {
+                return Ok(None);
+            }
+
+            let body = self.pending[1..end].to_vec();
+            let checksum = std::str::from_utf8(&self.pending[end + 1..end + 3]).ok()
+                .and_then(|text| u8::from_str_radix(text, 16).ok());
+            self.pending.drain(..end + 3);
+
+            let expected = body.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+            if checksum != Some(expected) && !self.no_ack {
+                self.stream.write_all(b"-")?;
+                continue;
+            }
+            if !self.no_ack {
+                self.stream.write_all(b"+")?;
+            }
+
+            return Ok(Some(Received::Packet(body)));
+        }
+    }
+}
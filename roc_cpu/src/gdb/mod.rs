@@ -0,0 +1,467 @@
+//! A GDB remote serial protocol stub, so that GDB (or
+//! anything else that speaks the protocol) can debug a
+//! program running on a `RocCPURunner`.
+//!
+//! GDB sees 13 registers: the ten general registers in
+//! `RocCPURegister::ALL` order, one byte each, then the
+//! program counter (two bytes, little-endian), the stack
+//! pointer and the flags (one byte each). The flags byte
+//! has zero, carry, negative and overflow in bits 0 to 3.
+//!
+//! Memory is the runner's 64K of memory. In
+//! `RocCPUExecutionMode::InstructionList` the program
+//! counter is an instruction index, so breakpoints are
+//! set on indexes, not addresses.
+
+mod connection;
+
+use std::io;
+use std::net::{TcpListener, ToSocketAddrs};
+
+use roc_cpu_types::*;
+
+use crate::runner::*;
+use connection::{Connection, Received, RocCPUGdbStream};
+
+/// How many instructions `continue` runs between
+/// checks for GDB interrupting it.
+const STEPS_BETWEEN_INTERRUPT_CHECKS: usize = 1024;
+
+const PC_REGISTER: usize = 10;
+const SP_REGISTER: usize = 11;
+const FLAGS_REGISTER: usize = 12;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGFPE: u8 = 8;
+const SIGSEGV: u8 = 11;
+
+
+/// Serves one GDB connection at a time, for a runner
+/// with a program already loaded.
+pub struct RocCPUGdbServer<'a> {
+    runner: &'a mut RocCPURunner,
+
+    /// The program's exit code, once it has exited.
+    exit_code: Option<u8>,
+}
+
+/// What to do after handling a packet.
+enum Reply {
+    Send(Vec<u8>),
+    SendAndClose(Vec<u8>),
+    Close,
+}
+
+impl<'a> RocCPUGdbServer<'a> {
+    pub fn new(runner: &'a mut RocCPURunner) -> Self {
+        Self { runner, exit_code: None }
+    }
+
+    /// Waits for GDB to connect on `address`, then serves
+    /// it until it detaches, kills the program or hangs up.
+    pub fn listen_tcp<A: ToSocketAddrs>(&mut self, address: A) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        self.serve(stream)
+    }
+
+    /// Like `listen_tcp`, on a Unix socket at `path`.
+    #[cfg(unix)]
+    pub fn listen_unix<P: AsRef<std::path::Path>>(&mut self, path: P) -> io::Result<()> {
+        let listener = std::os::unix::net::UnixListener::bind(path)?;
+        let (stream, _) = listener.accept()?;
+        self.serve(stream)
+    }
+
+    fn serve<S: RocCPUGdbStream>(&mut self, stream: S) -> io::Result<()> {
+        let mut connection = Connection::new(stream);
+
+        while let Some(received) = connection.receive()? {
+            let packet = match received {
+                Received::Packet(packet) => packet,
+                // Nothing is running, so there's nothing to stop.
+                Received::Interrupt => continue,
+            };
+
+            match self.handle(&packet, &mut connection)? {
+                Reply::Send(body) => connection.send(&body)?,
+                Reply::SendAndClose(body) => {
+                    connection.send(&body)?;
+                    break;
+                },
+                Reply::Close => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle<S: RocCPUGdbStream>(&mut self, packet: &[u8], connection: &mut Connection<S>) -> io::Result<Reply> {
+        let packet = String::from_utf8_lossy(packet);
+        let (command, args) = packet.split_at(packet.len().min(1));
+
+        let reply = match command {
+            "?" => self.stop_reply(SIGTRAP),
+            "g" => self.read_registers(),
+            "G" => ok_or_error(self.write_registers(args)),
+            "p" => self.read_register(args).unwrap_or_else(error),
+            "P" => ok_or_error(self.write_register(args)),
+            "m" => self.read_memory(args).unwrap_or_else(error),
+            "M" => ok_or_error(self.write_memory(args)),
+            "Z" => self.breakpoint(args, true),
+            "z" => self.breakpoint(args, false),
+            "s" => match self.resume_at(args) {
+                Ok(()) => self.step(),
+                Err(err) => error(err),
+            },
+            "c" => match self.resume_at(args) {
+                Ok(()) => self.continue_running(connection)?,
+                Err(err) => error(err),
+            },
+            "H" | "T" => b"OK".to_vec(),
+            "k" => return Ok(Reply::Close),
+            "D" => return Ok(Reply::SendAndClose(b"OK".to_vec())),
+            "q" | "Q" | "v" => return Ok(self.query(&packet, connection)),
+            _ => vec![],
+        };
+
+        Ok(Reply::Send(reply))
+    }
+
+    /// `q`, `Q` and `v` packets, which are named
+    /// rather than a single letter.
+    fn query<S: RocCPUGdbStream>(&mut self, packet: &str, connection: &mut Connection<S>) -> Reply {
+        let reply = if packet.starts_with("qSupported") {
+            b"PacketSize=1000;qXfer:features:read+;QStartNoAckMode+".to_vec()
+        } else if packet == "QStartNoAckMode" {
+            connection.no_ack = true;
+            b"OK".to_vec()
+        } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            target_description(range).unwrap_or_else(error)
+        } else {
+            match packet {
+                "qAttached" => b"1".to_vec(),
+                "qC" => b"QC1".to_vec(),
+                "qfThreadInfo" => b"m1".to_vec(),
+                "qsThreadInfo" => b"l".to_vec(),
+                _ => vec![],
+            }
+        };
+        Reply::Send(reply)
+    }
+
+
+    fn read_registers(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for index in 0..=FLAGS_REGISTER {
+            bytes.extend(self.register_bytes(index));
+        }
+        hex_encode(&bytes).into_bytes()
+    }
+
+    fn write_registers(&mut self, args: &str) -> Result<(), &'static str> {
+        let mut bytes = hex_decode(args)?.into_iter();
+        for index in 0..=FLAGS_REGISTER {
+            let width = self.register_bytes(index).len();
+            let value: Vec<_> = bytes.by_ref().take(width).collect();
+            if value.len() < width {
+                return Err("too few register bytes");
+            }
+            self.set_register_bytes(index, &value);
+        }
+        Ok(())
+    }
+
+    fn read_register(&self, args: &str) -> Result<Vec<u8>, &'static str> {
+        let index = parse_hex(args)?;
+        if index > FLAGS_REGISTER {
+            return Err("no such register");
+        }
+        Ok(hex_encode(&self.register_bytes(index)).into_bytes())
+    }
+
+    fn write_register(&mut self, args: &str) -> Result<(), &'static str> {
+        let (index, value) = args.split_once('=').ok_or("missing value")?;
+        let index = parse_hex(index)?;
+        let value = hex_decode(value)?;
+        if index > FLAGS_REGISTER || value.len() != self.register_bytes(index).len() {
+            return Err("no such register");
+        }
+        self.set_register_bytes(index, &value);
+        Ok(())
+    }
+
+    /// Register `index`, in the bytes GDB expects.
+    fn register_bytes(&self, index: usize) -> Vec<u8> {
+        match index {
+            PC_REGISTER => (self.runner.program_counter() as u16).to_le_bytes().to_vec(),
+            SP_REGISTER => vec![self.runner.stack_pointer() as u8],
+            FLAGS_REGISTER => vec![self.runner.flags().to_bits()],
+            _ => vec![self.runner.register(RocCPURegister::ALL[index])],
+        }
+    }
+
+    fn set_register_bytes(&mut self, index: usize, bytes: &[u8]) {
+        match index {
+            PC_REGISTER => {
+                let pc = u16::from_le_bytes([bytes[0], bytes[1]]);
+                self.runner.set_program_counter(pc as usize);
+            },
+            SP_REGISTER => self.runner.set_stack_pointer(bytes[0] as usize),
+            FLAGS_REGISTER => self.runner.set_flags(RocCPUFlags::from_bits(bytes[0])),
+            _ => self.runner.set_register(RocCPURegister::ALL[index], bytes[0]),
+        }
+    }
+
+
+    fn read_memory(&self, args: &str) -> Result<Vec<u8>, &'static str> {
+        let (address, length) = parse_range(args)?;
+        let memory = self.runner.memory();
+        if address >= memory.len() {
+            return Err("address out of range");
+        }
+        let end = address.saturating_add(length).min(memory.len());
+        Ok(hex_encode(&memory[address..end]).into_bytes())
+    }
+
+    fn write_memory(&mut self, args: &str) -> Result<(), &'static str> {
+        let (range, data) = args.split_once(':').ok_or("missing data")?;
+        let (address, length) = parse_range(range)?;
+        let data = hex_decode(data)?;
+
+        let memory = self.runner.memory_mut();
+        let end = address.checked_add(length).ok_or("bad memory write")?;
+        if data.len() != length || end > memory.len() {
+            return Err("bad memory write");
+        }
+        memory[address..end].copy_from_slice(&data);
+        Ok(())
+    }
+
+    /// `Z` and `z`. Hardware breakpoints are treated just
+    /// like software ones, and watchpoints aren't supported.
+    fn breakpoint(&mut self, args: &str, insert: bool) -> Vec<u8> {
+        let mut parts = args.split(',');
+        let kind = parts.next();
+        let address = parts.next().map(parse_hex);
+
+        match (kind, address) {
+            (Some("0") | Some("1"), Some(Ok(address))) => {
+                if insert {
+                    self.runner.add_breakpoint(address);
+                } else {
+                    self.runner.remove_breakpoint(address);
+                }
+                b"OK".to_vec()
+            },
+            (Some("0") | Some("1"), _) => error("bad address"),
+            _ => vec![],
+        }
+    }
+
+
+    /// `s` and `c` can give an address to resume from.
+    fn resume_at(&mut self, args: &str) -> Result<(), &'static str> {
+        if !args.is_empty() {
+            self.runner.set_program_counter(parse_hex(args)?);
+        }
+        Ok(())
+    }
+
+    fn step(&mut self) -> Vec<u8> {
+        match self.runner.step() {
+            RocCPUStepOutcome::Continued | RocCPUStepOutcome::Waiting(_) => self.stop_reply(SIGTRAP),
+            outcome => self.outcome_reply(outcome),
+        }
+    }
+
+    /// Runs until a breakpoint, exit or fault, or until
+    /// GDB interrupts. `Wait` doesn't pause.
+    fn continue_running<S: RocCPUGdbStream>(&mut self, connection: &mut Connection<S>) -> io::Result<Vec<u8>> {
+        let mut steps = 0;
+        loop {
+            match self.runner.step() {
+                RocCPUStepOutcome::Continued | RocCPUStepOutcome::Waiting(_) => {},
+                outcome => return Ok(self.outcome_reply(outcome)),
+            }
+
+            if self.runner.is_breakpoint(self.runner.program_counter()) {
+                return Ok(self.stop_reply(SIGTRAP));
+            }
+
+            steps += 1;
+            if steps % STEPS_BETWEEN_INTERRUPT_CHECKS == 0 && connection.interrupted()? {
+                return Ok(self.stop_reply(SIGINT));
+            }
+        }
+    }
+
+    /// The reply for a program that exited or faulted.
+    fn outcome_reply(&mut self, outcome: RocCPUStepOutcome) -> Vec<u8> {
+        match outcome {
+            RocCPUStepOutcome::Exited => {
+                let code = self.runner.state().return_value();
+                self.exit_code = Some(code);
+                format!("W{:02x}", code).into_bytes()
+            },
+            RocCPUStepOutcome::Faulted(fault) => {
+                let signal = match fault {
+                    RocCPUFault::DivideByZero { .. } => SIGFPE,
+                    RocCPUFault::UnknownOpcode { .. } => SIGILL,
+                    _ => SIGSEGV,
+                };
+                self.stop_reply(signal)
+            },
+            RocCPUStepOutcome::Continued | RocCPUStepOutcome::Waiting(_) => self.stop_reply(SIGTRAP),
+        }
+    }
+
+    fn stop_reply(&self, signal: u8) -> Vec<u8> {
+        match self.exit_code {
+            Some(code) => format!("W{:02x}", code).into_bytes(),
+            None => format!("S{:02x}", signal).into_bytes(),
+        }
+    }
+}
+
+
+/// The register layout, for GDB to read with
+/// `qXfer:features:read`.
+fn target_description(range: &str) -> Result<Vec<u8>, &'static str> {
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\"?>",
+        "<!DOCTYPE target SYSTEM \"gdb-target.dtd\">",
+        "<target version=\"1.0\"><feature name=\"org.roc.cpu\">",
+    ));
+    for register in RocCPURegister::ALL {
+        let name = register.to_string();
+        xml += &format!("<reg name=\"{}\" bitsize=\"8\"/>", name.trim_start_matches('$'));
+    }
+    xml += "<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>";
+    xml += "<reg name=\"sp\" bitsize=\"8\"/>";
+    xml += "<reg name=\"flags\" bitsize=\"8\"/>";
+    xml += "</feature></target>";
+
+    let (offset, length) = parse_range(range)?;
+    let xml = xml.as_bytes();
+    let start = offset.min(xml.len());
+    let end = start.saturating_add(length).min(xml.len());
+
+    let mut reply = vec![if end == xml.len() { b'l' } else { b'm' }];
+    reply.extend(&xml[start..end]);
+    Ok(reply)
+}
+
+fn ok_or_error(result: Result<(), &'static str>) -> Vec<u8> {
+    match result {
+        Ok(()) => b"OK".to_vec(),
+        Err(message) => error(message),
+    }
+}
+
+/// GDB only looks at the number, the message is
+/// there for anyone reading a packet log.
+fn error(_message: &str) -> Vec<u8> {
+    b"E01".to_vec()
+}
+
+/// `address,length`, both in hex.
+fn parse_range(text: &str) -> Result<(usize, usize), &'static str> {
+    let (address, length) = text.split_once(',').ok_or("missing length")?;
+    Ok((parse_hex(address)?, parse_hex(length)?))
+}
+
+fn parse_hex(text: &str) -> Result<usize, &'static str> {
+    usize::from_str_radix(text, 16).map_err(|_| "bad number")
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_decode(text: &str) -> Result<Vec<u8>, &'static str> {
+    if !text.len().is_multiple_of(2) {
+        return Err("odd number of hex digits");
+    }
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair).ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or("bad hex")
+        })
+        .collect()
+}
+
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Serves a fresh runner on a Unix socket, and
+    /// connects to it.
+    fn connect(name: &str) -> (UnixStream, std::thread::JoinHandle<io::Result<()>>) {
+        let path = std::env::temp_dir().join(format!("roc_gdb_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let server_path = path.clone();
+        let server = std::thread::spawn(move || {
+            let mut runner = RocCPURunner::default();
+            RocCPUGdbServer::new(&mut runner).listen_unix(&server_path)
+        });
+
+        for _ in 0..100 {
+            if let Ok(stream) = UnixStream::connect(&path) {
+                stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+                let _ = std::fs::remove_file(&path);
+                return (stream, server);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("the stub never started listening");
+    }
+
+    /// Sends a packet and returns the body of the reply.
+    fn exchange(stream: &mut UnixStream, body: &str) -> String {
+        let checksum = body.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(stream, "${}#{:02x}", body, checksum).unwrap();
+
+        let mut received = vec![];
+        let mut byte = [0];
+        while byte[0] != b'#' {
+            stream.read_exact(&mut byte).unwrap();
+            received.push(byte[0]);
+        }
+        let mut checksum = [0; 2];
+        stream.read_exact(&mut checksum).unwrap();
+
+        let text = String::from_utf8(received).unwrap();
+        let start = text.find('$').unwrap() + 1;
+        let end = text.len() - 1;
+        text[start..end].to_string()
+    }
+
+    #[test]
+    fn ranges_that_overflow_are_errors() {
+        let (mut stream, server) = connect("overflow");
+
+        // Reads are cut short at the end of memory.
+        assert_eq!(exchange(&mut stream, "m1,ffffffffffffffff").len(), 0xffff * 2);
+        assert_eq!(exchange(&mut stream, "Mffffffffffffffff,2:4142"), "E01");
+        assert!(exchange(&mut stream, "qXfer:features:read:target.xml:1,ffffffffffffffff").starts_with('l'));
+
+        // Still serving afterwards.
+        assert_eq!(exchange(&mut stream, "M4000,2:4142"), "OK");
+        assert_eq!(exchange(&mut stream, "m4000,2"), "4142");
+
+        stream.write_all(b"$k#6b").unwrap();
+        server.join().unwrap().unwrap();
+    }
+}
@@ -1,12 +1,38 @@
+mod binary;
+mod gdb;
 mod runner;
-mod types;
+mod trace;
 
-pub use types::*;
+pub use binary::*;
+pub use gdb::*;
+pub use roc_cpu_types::*;
 pub use runner::*;
+pub use trace::*;
 
-pub use roc_cpu_proc::roc_asm;
+pub use roc_cpu_asm_macro::roc_asm;
+
+/// Assembling Roc assembly source text at runtime,
+/// and disassembling programs back into it.
+pub mod asm {
+    pub use roc_cpu_asm::{
+        assemble,
+        assemble_file,
+        assemble_file_with_listing,
+        assemble_file_with_warnings,
+        assemble_to_image,
+        assemble_with_listing,
+        assemble_with_warnings,
+        disassemble,
+        disassemble_image,
+        disassemble_with_labels,
+        RocAsmDiagnostic,
+        RocAsmError,
+        RocAsmListing,
+        RocAsmListingLine,
+        RocAsmListingLocation,
+    };
+}
 
 pub mod prelude {
     pub use roc_cpu_traits::*;
 }
-
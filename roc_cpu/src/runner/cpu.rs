@@ -1,11 +1,24 @@
-use crate::types::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+use roc_cpu_types::*;
+use crate::binary::*;
 use crate::runner::display::*;
+use crate::runner::flags::RocCPUFlags;
+use crate::runner::outcome::RocCPUStepOutcome;
+use crate::runner::fault::RocCPUFault;
+use crate::runner::state::RocCPUState;
+use crate::runner::mode::RocCPUExecutionMode;
+use crate::runner::watchpoint::*;
+use crate::trace::*;
+use roc_cpu_traits::{ProgramDecodable, ProgramDecodeError};
 
 pub struct RocCPURunner {
-    display: RocCPUDisplay,
+    display: Box<dyn RocCPUDisplayBackend>,
 
     registers: [u8; 10],
-    program: Option<Vec<RocCPUInstruction>>,
+    program: Option<RocCPUProgram>,
+    execution_mode: RocCPUExecutionMode,
+    binary: Option<RocBinary>,
     memory: [u8; 0x10000],
     stack: [u8; 0xFF],
 
@@ -16,28 +29,63 @@ pub struct RocCPURunner {
     /// This points at the value one idx ahead
     /// of the most recently populated stack val
     stack_pointer: usize,
+    next_program_counter: usize,
     pc_manually_set: bool,
+    pending_wait: Option<std::time::Duration>,
+    fault: Option<RocCPUFault>,
+
+    flags: RocCPUFlags,
+
+    breakpoints: BTreeSet<usize>,
+
+    watchpoints: BTreeMap<usize, RocCPUWatchpoint>,
+    next_watchpoint_id: usize,
 
-    // Flags
-    zero_flag: bool,
+    /// Watchpoints that memory accesses by the instruction
+    /// being executed have set off so far.
+    memory_watch_hits: Vec<(usize, RocCPUWatchpointEvent)>,
+    watchpoint_hits: Vec<RocCPUWatchpointHit>,
+
+    tracer: Option<Box<dyn RocCPUTracer>>,
+
+    /// What the instruction being executed has done
+    /// so far, while tracing.
+    trace_entry: Option<RocCPUTraceEntry>,
+    traced_steps: u64,
 }
 
 impl Default for RocCPURunner {
     fn default() -> Self {
         Self {
-            display: RocCPUDisplay::new(),
+            display: Box::new(RocCPUNullDisplay),
 
             registers: [0; 10],
             program: None,
+            execution_mode: RocCPUExecutionMode::InstructionList,
+            binary: None,
             memory: [0; 0x10000],
             stack: [0; 0xFF],
 
             should_continue: true,
             program_counter: 0,
             stack_pointer: 0,
+            next_program_counter: 0,
             pc_manually_set: false,
+            pending_wait: None,
+            fault: None,
+
+            flags: RocCPUFlags::default(),
+
+            breakpoints: BTreeSet::new(),
 
-            zero_flag: false,
+            watchpoints: BTreeMap::new(),
+            next_watchpoint_id: 1,
+            memory_watch_hits: vec![],
+            watchpoint_hits: vec![],
+
+            tracer: None,
+            trace_entry: None,
+            traced_steps: 0,
         }
     }
 }
@@ -45,89 +93,552 @@ impl Default for RocCPURunner {
 // Public API Implementations
 impl RocCPURunner {
 
-    pub fn new(program: Option<&Vec<RocCPUInstruction>>) -> Self {
+    /// Creates a runner that sends every `Render` to
+    /// `display`. `RocCPURunner::default()` uses a
+    /// `RocCPUNullDisplay`, so it never opens a window.
+    pub fn new(
+        program: Option<&RocCPUProgram>,
+        display: Box<dyn RocCPUDisplayBackend>
+    ) -> Self {
 
         let mut s = Self {
+            display,
             ..Default::default()
         };
 
-        if program.is_some() {
-            let newprogram = program.unwrap().clone();
-            s.program = Some(newprogram);
+        if let Some(program) = program {
+            s.load_program(program);
         }
 
         s
     }
 
 
-    pub fn load_program(&mut self, program: &Vec<RocCPUInstruction>) {
+    /// Loads `program` to run straight from its instruction
+    /// list, and copies its data segments into memory.
+    pub fn load_program(&mut self, program: &RocCPUProgram) {
+        for segment in &program.data {
+            self.copy_into_memory(&RocBinarySection {
+                load_address: segment.address,
+                bytes: segment.bytes.clone(),
+            });
+        }
+
         self.program = Some(program.clone());
+        self.binary = None;
+        self.execution_mode = RocCPUExecutionMode::InstructionList;
+    }
+
+    /// Encodes `program` into memory at `load_address` and
+    /// switches to `RocCPUExecutionMode::Memory`. Jump and
+    /// call targets are relocated from instruction indexes
+    /// to byte addresses, see `encode_program`. Data
+    /// segments are copied in too, unless the code would
+    /// overlap them, which leaves memory untouched.
+    pub fn load_program_into_memory(
+        &mut self,
+        program: &RocCPUProgram,
+        load_address: u16
+    ) -> Result<(), RocBinaryError> {
+        self.load_binary(&RocBinary::from_program(program, load_address)?);
+        Ok(())
+    }
+
+    /// Copies already-encoded instructions into memory at
+    /// `load_address` and switches to
+    /// `RocCPUExecutionMode::Memory`. Jump and call targets
+    /// must already be byte addresses.
+    pub fn load_image_into_memory(&mut self, image: &[u8], load_address: u16) {
+        self.load_binary(&RocBinary::from_image(image.to_vec(), load_address));
+    }
+
+    /// Copies every section of `binary` into memory and
+    /// switches to `RocCPUExecutionMode::Memory`, ready to
+    /// start at the binary's entry point.
+    ///
+    /// Anything past the end of memory is dropped.
+    pub fn load_binary(&mut self, binary: &RocBinary) {
+        for section in &binary.data {
+            self.copy_into_memory(section);
+        }
+        self.copy_into_memory(&binary.code);
+
+        self.program = None;
+        self.binary = Some(binary.clone());
+        self.execution_mode = RocCPUExecutionMode::Memory { entry_point: binary.entry_point };
+        self.reset();
+    }
+
+    pub fn load_binary_file<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<(), RocBinaryError> {
+        let binary = RocBinary::read_file(path)?;
+        self.load_binary(&binary);
+        Ok(())
+    }
+
+    /// The loaded program as a binary. Programs loaded with
+    /// `load_program` are encoded to run from address 0.
+    /// Programs in memory are returned as they were loaded,
+    /// without any changes made to memory since. Without a
+    /// program, this is `RocBinaryError::NoProgram`.
+    pub fn to_binary(&self) -> Result<RocBinary, RocBinaryError> {
+        let binary = match self.execution_mode {
+            RocCPUExecutionMode::InstructionList => {
+                self.program.as_ref().map(|p| RocBinary::from_program(p, 0)).transpose()?
+            },
+            RocCPUExecutionMode::Memory { .. } => self.binary.clone(),
+        };
+        binary.ok_or(RocBinaryError::NoProgram)
+    }
+
+    /// The symbols of the loaded binary, if any.
+    pub fn symbols(&self) -> &[RocBinarySymbol] {
+        self.binary.as_ref().map_or(&[], |b| b.symbols.as_slice())
+    }
+
+    pub fn write_binary_file<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), RocBinaryError> {
+        self.to_binary()?.write_file(path)
     }
 
     pub fn unload_program(&mut self) {
         self.program = None;
+        self.binary = None;
+        self.execution_mode = RocCPUExecutionMode::InstructionList;
     }
 
-    pub fn execute(&mut self) -> u8 {
-        self.reset_execution_stuff();
+    pub fn execution_mode(&self) -> RocCPUExecutionMode {
+        self.execution_mode
+    }
+
+    /// Runs the loaded program from the start until it
+    /// exits or faults. `Wait` instructions block the
+    /// calling thread. The return value is in `$ret`, see
+    /// `RocCPUState::return_value`.
+    pub fn execute(&mut self) -> Result<RocCPUState, RocCPUFault> {
+        self.reset();
+
+        if !self.has_program() {
+            return Ok(self.state());
+        }
 
-        if self.program.is_none() {
-            return 0;
+        loop {
+            match self.step() {
+                RocCPUStepOutcome::Continued => {},
+                RocCPUStepOutcome::Waiting(duration) => {
+                    std::thread::sleep(duration);
+                },
+                RocCPUStepOutcome::Exited => break,
+                RocCPUStepOutcome::Faulted(fault) => return Err(fault),
+            }
         }
 
-        self.execution_mainloop();
-        self.get_register_value(RocCPURegister::ReturnValue)
+        Ok(self.state())
     }
-}
 
+    /// Moves execution back to the start of the program
+    /// and empties the stack. Registers, flags and memory
+    /// are left alone.
+    pub fn reset(&mut self) {
+        self.reset_execution_stuff();
+    }
 
-// Execution Mainloop
+    /// Executes exactly one instruction. Unlike `execute`,
+    /// this never blocks: a `Wait` instruction is reported
+    /// as `RocCPUStepOutcome::Waiting`, and it is up to the
+    /// caller to wait before stepping again.
+    pub fn step(&mut self) -> RocCPUStepOutcome {
+        self.watchpoint_hits.clear();
 
-impl RocCPURunner {
+        if !self.has_program() {
+            return RocCPUStepOutcome::Exited;
+        }
 
-    fn execution_mainloop(&mut self) {
-        
-        'execution_loop: loop {
+        if let Some(fault) = self.fault {
+            return RocCPUStepOutcome::Faulted(fault);
+        }
 
-            if self.program.as_ref().unwrap().len() <= self.program_counter {
-                self.set_register_value(RocCPURegister::ReturnValue, 255);
-                break 'execution_loop;
+        if !self.should_continue {
+            return RocCPUStepOutcome::Exited;
+        }
+
+        let (opcode, length) = match self.current_instruction() {
+            Ok(fetched) => fetched,
+            Err(fault) => {
+                self.fault = Some(fault);
+                return RocCPUStepOutcome::Faulted(fault);
             }
+        };
 
-            let opcode = self.program.as_ref().unwrap()[self.program_counter];
-            self.execute_opcode(opcode);
+        self.next_program_counter = self.program_counter + length;
+        let pc = self.program_counter;
+        let registers_before = self.registers;
+        let flags_before = self.flags;
+        let stack_pointer_before = self.stack_pointer;
+        if self.tracer.is_some() {
+            self.trace_entry = Some(RocCPUTraceEntry::new(self.traced_steps, self.program_counter, opcode));
+        }
+
+        if let Err(fault) = self.execute_opcode(opcode) {
+            self.trace_entry = None;
+            self.memory_watch_hits.clear();
+            self.fault = Some(fault);
+            return RocCPUStepOutcome::Faulted(fault);
+        }
+        self.finish_trace_entry(registers_before, flags_before);
+        self.check_watchpoints(pc, opcode, registers_before, stack_pointer_before);
+
+        if !self.should_continue {
+            return RocCPUStepOutcome::Exited;
+        }
+
+        if !self.pc_manually_set {
+            self.program_counter = self.next_program_counter;
+        }
+        self.pc_manually_set = false;
 
-            if !self.should_continue {
-                break 'execution_loop;
+        match self.pending_wait.take() {
+            Some(duration) => RocCPUStepOutcome::Waiting(duration),
+            None => RocCPUStepOutcome::Continued,
+        }
+    }
+
+    /// Executes up to `steps` instructions, stopping early
+    /// on anything other than `RocCPUStepOutcome::Continued`.
+    /// Returns the outcome of the last instruction executed.
+    pub fn run_for(&mut self, steps: usize) -> RocCPUStepOutcome {
+        let mut outcome = RocCPUStepOutcome::Continued;
+        for _ in 0..steps {
+            outcome = self.step();
+            if outcome != RocCPUStepOutcome::Continued {
+                break;
             }
+        }
+        outcome
+    }
 
-            if !self.pc_manually_set {
-                self.program_counter += 1;
+    /// Executes instructions until `predicate` returns true,
+    /// checking it before every instruction. Also stops early
+    /// on anything other than `RocCPUStepOutcome::Continued`.
+    pub fn run_until<F>(&mut self, mut predicate: F) -> RocCPUStepOutcome
+        where F: FnMut(&RocCPURunner) -> bool
+    {
+        loop {
+            if predicate(self) {
+                return RocCPUStepOutcome::Continued;
             }
-            self.pc_manually_set = false;
+
+            let outcome = self.step();
+            if outcome != RocCPUStepOutcome::Continued {
+                return outcome;
+            }
+        }
+    }
+
+    /// A snapshot of the registers, flags and pointers.
+    pub fn state(&self) -> RocCPUState {
+        RocCPUState {
+            registers: self.registers,
+            flags: self.flags,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+        }
+    }
+
+    /// The fault that stopped the program, if any.
+    /// Cleared by `reset`.
+    pub fn fault(&self) -> Option<RocCPUFault> {
+        self.fault
+    }
+
+    /// The status flags left by the most recent
+    /// arithmetic or comparison instruction.
+    pub fn flags(&self) -> RocCPUFlags {
+        self.flags
+    }
+
+    /// The next instruction to execute: an index into the
+    /// program, or a byte address in memory, depending on
+    /// the `RocCPUExecutionMode`.
+    pub fn program_counter(&self) -> usize {
+        self.program_counter
+    }
+
+    pub fn register(&self, register: RocCPURegister) -> u8 {
+        self.get_register_value(register)
+    }
+
+    pub fn set_register(&mut self, register: RocCPURegister, value: u8) {
+        self.set_register_value(register, value);
+    }
+
+    pub fn set_flags(&mut self, flags: RocCPUFlags) {
+        self.flags = flags;
+    }
+
+    /// Moves execution to `pc`, without checking that
+    /// there is an instruction there.
+    pub fn set_program_counter(&mut self, pc: usize) {
+        self.program_counter = pc;
+    }
+
+    /// How many bytes are currently on the stack.
+    pub fn stack_pointer(&self) -> usize {
+        self.stack_pointer
+    }
+
+    /// Moves the stack pointer, which can't go past
+    /// the end of the stack. Values uncovered by moving
+    /// it up are whatever was last left there.
+    pub fn set_stack_pointer(&mut self, stack_pointer: usize) {
+        self.stack_pointer = stack_pointer.min(self.stack.len());
+    }
+
+    /// The values currently on the stack, from
+    /// the bottom up.
+    pub fn stack(&self) -> &[u8] {
+        &self.stack[..self.stack_pointer]
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Memory, for a debugger to change. Nothing is
+    /// rendered until the program next executes `Render`.
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    /// The instruction at the program counter, and how
+    /// many bytes (or list entries) it takes up.
+    pub fn current_instruction(&self) -> Result<(RocCPUInstruction, usize), RocCPUFault> {
+        self.instruction_at(self.program_counter)
+    }
+
+    /// The instruction at `pc`, read the same way the
+    /// program counter would read it.
+    pub fn instruction_at(&self, pc: usize) -> Result<(RocCPUInstruction, usize), RocCPUFault> {
+        let out_of_bounds = RocCPUFault::ProgramCounterOutOfBounds { pc };
+
+        match self.execution_mode {
+            RocCPUExecutionMode::InstructionList => {
+                let program = self.program.as_ref().ok_or(out_of_bounds)?;
+                let opcode = program.get(pc).ok_or(out_of_bounds)?;
+                Ok((*opcode, 1))
+            },
+            RocCPUExecutionMode::Memory { .. } => {
+                let bytes = self.memory.get(pc..).ok_or(out_of_bounds)?;
+                match RocCPUInstruction::decode(bytes) {
+                    Ok(decoded) => Ok(decoded),
+                    Err(ProgramDecodeError::UnknownDiscriminant(opcode)) => {
+                        Err(RocCPUFault::UnknownOpcode { pc, opcode })
+                    },
+                    Err(ProgramDecodeError::UnexpectedEnd) => Err(out_of_bounds),
+                }
+            },
+        }
+    }
+
+    /// Marks `pc` as a breakpoint. Returns false if
+    /// it already was one.
+    ///
+    /// The runner itself never stops at breakpoints, it
+    /// only keeps them so that a debugger can check them
+    /// with `is_breakpoint` before each step.
+    pub fn add_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.insert(pc)
+    }
+
+    /// Returns false if `pc` wasn't a breakpoint.
+    pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn is_breakpoint(&self, pc: usize) -> bool {
+        self.breakpoints.contains(&pc)
+    }
+
+    /// Every breakpoint, lowest first.
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Watches for `watchpoint` from the next instruction
+    /// on. Returns an id for it, which hits report and
+    /// `remove_watchpoint` takes.
+    ///
+    /// Like breakpoints, the runner never stops for them.
+    /// Instructions that set one off still run to the end,
+    /// and a debugger checks `watchpoint_hits` after each
+    /// step.
+    pub fn add_watchpoint(&mut self, watchpoint: RocCPUWatchpoint) -> usize {
+        let id = self.next_watchpoint_id;
+        self.next_watchpoint_id += 1;
+        self.watchpoints.insert(id, watchpoint);
+        id
+    }
+
+    /// Returns false if there was no watchpoint with `id`.
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        self.watchpoints.remove(&id).is_some()
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// Every watchpoint and its id, oldest first.
+    pub fn watchpoints(&self) -> impl Iterator<Item = (usize, &RocCPUWatchpoint)> + '_ {
+        self.watchpoints.iter().map(|(id, watchpoint)| (*id, watchpoint))
+    }
+
+    /// The watchpoints the most recent instruction set
+    /// off, in the order it set them off. Cleared by the
+    /// next step, and by `reset`. Instructions that fault
+    /// don't set any off.
+    pub fn watchpoint_hits(&self) -> &[RocCPUWatchpointHit] {
+        &self.watchpoint_hits
+    }
+
+    /// Sends what every instruction executed from now on
+    /// does to `tracer`, counting steps from 0. Call
+    /// `stop_tracing` first to finish an earlier tracer.
+    pub fn set_tracer(&mut self, tracer: Box<dyn RocCPUTracer>) {
+        self.tracer = Some(tracer);
+        self.traced_steps = 0;
+    }
+
+    /// Takes the tracer off and finishes it, which is
+    /// when tracers that write report any error.
+    pub fn stop_tracing(&mut self) -> std::io::Result<()> {
+        match self.tracer.take() {
+            Some(mut tracer) => tracer.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.tracer.is_some()
+    }
+}
+
+
+// Execution Internals
+
+impl RocCPURunner {
+
+    fn has_program(&self) -> bool {
+        match self.execution_mode {
+            RocCPUExecutionMode::InstructionList => self.program.is_some(),
+            RocCPUExecutionMode::Memory { .. } => true,
         }
     }
 
-    fn push_value_to_stack(&mut self, val: u8) {
+    fn push_value_to_stack(&mut self, opcode: RocCPUInstruction, val: u8) -> Result<(), RocCPUFault> {
         if self.stack_pointer == self.stack.len() {
-            panic!( "Tried to push a value onto a full stack." );
+            return Err(RocCPUFault::StackOverflow {
+                pc: self.program_counter,
+                instruction: opcode,
+            });
         }
 
         self.stack[self.stack_pointer] = val;
         self.stack_pointer += 1;
+        if let Some(entry) = &mut self.trace_entry {
+            entry.stack.push(RocCPUStackOperation::Push(val));
+        }
+        Ok(())
     }
 
-    fn pop_value_from_stack(&mut self) -> u8 {
+    fn pop_value_from_stack(&mut self, opcode: RocCPUInstruction) -> Result<u8, RocCPUFault> {
         if self.stack_pointer == 0 {
-            panic!( "Tried to pop a value from an empty stack." );
+            return Err(RocCPUFault::StackUnderflow {
+                pc: self.program_counter,
+                instruction: opcode,
+            });
         }
 
         self.stack_pointer -= 1;
-        self.stack[self.stack_pointer]
+        let val = self.stack[self.stack_pointer];
+        if let Some(entry) = &mut self.trace_entry {
+            entry.stack.push(RocCPUStackOperation::Pop(val));
+        }
+        Ok(val)
+    }
+
+    // ALU helpers. Each of these computes the 8-bit result
+    // and updates every status flag from it.
+
+    fn alu_add(&mut self, a: u8, b: u8) -> u8 {
+        let (retval, carry) = a.overflowing_add(b);
+        let (_, overflow) = (a as i8).overflowing_add(b as i8);
+        self.flags = RocCPUFlags::from_result(retval, carry, overflow);
+        retval
     }
 
+    fn alu_sub(&mut self, a: u8, b: u8) -> u8 {
+        let (retval, borrow) = a.overflowing_sub(b);
+        let (_, overflow) = (a as i8).overflowing_sub(b as i8);
+        self.flags = RocCPUFlags::from_result(retval, borrow, overflow);
+        retval
+    }
+
+    fn alu_mul(&mut self, a: u8, b: u8) -> u8 {
+        let (retval, carry) = a.overflowing_mul(b);
+        let (_, overflow) = (a as i8).overflowing_mul(b as i8);
+        self.flags = RocCPUFlags::from_result(retval, carry, overflow);
+        retval
+    }
+
+    /// Integer division, rounding towards zero. Dividing
+    /// by zero is a CPU fault rather than a silent result.
+    fn alu_div(&mut self, opcode: RocCPUInstruction, a: u8, b: u8) -> Result<u8, RocCPUFault> {
+        let retval = match a.checked_div(b) {
+            Some(val) => val,
+            None => {
+                return Err(RocCPUFault::DivideByZero {
+                    pc: self.program_counter,
+                    instruction: opcode,
+                });
+            }
+        };
+        self.flags = RocCPUFlags::from_result(retval, false, false);
+        Ok(retval)
+    }
+
+    fn jump_if(&mut self, opcode: RocCPUInstruction, condition: bool, hi: u8, lo: u8) -> Result<(), RocCPUFault> {
+        if condition {
+            let hi = (hi as usize) << 8;
+            let address: usize = hi + lo as usize;
+            self.jump_to(opcode, address)?;
+        }
+        Ok(())
+    }
+
+    /// Moves execution to `address`, which must be
+    /// inside the loaded program.
+    fn jump_to(&mut self, opcode: RocCPUInstruction, address: usize) -> Result<(), RocCPUFault> {
+        let program_len = match self.execution_mode {
+            RocCPUExecutionMode::InstructionList => self.program.as_ref().map_or(0, |p| p.len()),
+            RocCPUExecutionMode::Memory { .. } => self.memory.len(),
+        };
+        if address >= program_len {
+            return Err(RocCPUFault::InvalidJumpTarget {
+                pc: self.program_counter,
+                instruction: opcode,
+                target: address,
+            });
+        }
+
+        self.program_counter = address;
+        self.pc_manually_set = true;
+        Ok(())
+    }
 
-    fn execute_opcode(&mut self, opcode: RocCPUInstruction) {
+    fn execute_opcode(&mut self, opcode: RocCPUInstruction) -> Result<(), RocCPUFault> {
 
         use crate::RocCPUInstruction::*;
         use crate::RocCPURegister::*;
@@ -137,28 +648,47 @@ impl RocCPURunner {
             // Arithmetic
 
             Add(dst, src) => {
-                let dst_val = self.get_register_value(dst);
                 let src_val = self.get_register_value(src);
-                let retval = match dst_val.checked_add(src_val) {
-                    Some(val) => val,
-                    None => { dst_val.wrapping_add(src_val) }
-                };
-
+                let retval = self.alu_add(self.get_register_value(dst), src_val);
                 self.set_register_value(dst, retval);
+            },
 
-                self.zero_flag = retval == 0;
+            AddI(dst, val) => {
+                let retval = self.alu_add(self.get_register_value(dst), val);
+                self.set_register_value(dst, retval);
             },
 
             Sub(dst, src) => {
-                let dst_val = self.get_register_value(dst);
                 let src_val = self.get_register_value(src);
-                let retval = match dst_val.checked_sub(src_val) {
-                    Some(val) => val,
-                    None => { dst_val.wrapping_sub(src_val) }
-                };
+                let retval = self.alu_sub(self.get_register_value(dst), src_val);
+                self.set_register_value(dst, retval);
+            },
+
+            SubI(dst, val) => {
+                let retval = self.alu_sub(self.get_register_value(dst), val);
+                self.set_register_value(dst, retval);
+            },
+
+            Mul(dst, src) => {
+                let src_val = self.get_register_value(src);
+                let retval = self.alu_mul(self.get_register_value(dst), src_val);
+                self.set_register_value(dst, retval);
+            },
+
+            MulI(dst, val) => {
+                let retval = self.alu_mul(self.get_register_value(dst), val);
+                self.set_register_value(dst, retval);
+            },
+
+            Div(dst, src) => {
+                let src_val = self.get_register_value(src);
+                let retval = self.alu_div(opcode, self.get_register_value(dst), src_val)?;
                 self.set_register_value(dst, retval);
+            },
 
-                self.zero_flag = retval == 0;
+            DivI(dst, val) => {
+                let retval = self.alu_div(opcode, self.get_register_value(dst), val)?;
+                self.set_register_value(dst, retval);
             },
 
             // Setting registers
@@ -185,57 +715,100 @@ impl RocCPURunner {
             Cmp(reg1, reg2) => {
                 let val1 = self.get_register_value(reg1);
                 let val2 = self.get_register_value(reg2);
-
-                match val1.checked_sub(val2) {
-                    Some(res) => {
-                        if res == 0 { self.zero_flag = true; }
-                        else { self.zero_flag = false; }
-                    },
-                    None => { self.zero_flag = false; }
-                }
+                self.alu_sub(val1, val2);
             },
 
 
             // Testing things
             
             PutMem(hi, lo, val) => {
-                let hi = (hi as usize) << 8;
-                let address: usize = hi + lo as usize;
-                self.memory[address] = val;
+                self.write_memory(u16::from_be_bytes([hi, lo]), val);
+            },
+
+            // Loads and stores leave the flags alone
+
+            Load(dst, hi, lo) => {
+                let val = self.read_memory(u16::from_be_bytes([hi, lo]));
+                self.set_register_value(dst, val);
+            },
+
+            Store(hi, lo, src) => {
+                let val = self.get_register_value(src);
+                self.write_memory(u16::from_be_bytes([hi, lo]), val);
+            },
+
+            LoadIndirect(dst, hi, lo) => {
+                let val = self.read_memory(self.get_register_pair(hi, lo));
+                self.set_register_value(dst, val);
+            },
+
+            StoreIndirect(hi, lo, src) => {
+                let val = self.get_register_value(src);
+                self.write_memory(self.get_register_pair(hi, lo), val);
+            },
+
+            LoadIndirectInc(dst, hi, lo) => {
+                let address = self.get_register_pair(hi, lo);
+                let val = self.read_memory(address);
+                self.set_register_value(dst, val);
+                self.set_register_pair(hi, lo, address.wrapping_add(1));
+            },
+
+            StoreIndirectInc(hi, lo, src) => {
+                let address = self.get_register_pair(hi, lo);
+                let val = self.get_register_value(src);
+                self.write_memory(address, val);
+                self.set_register_pair(hi, lo, address.wrapping_add(1));
             },
 
             Push(reg) => {
-                self.push_value_to_stack(self.get_register_value(reg));
+                self.push_value_to_stack(opcode, self.get_register_value(reg))?;
             },
 
             Pop(reg) => {
-                let val = self.pop_value_from_stack();
+                let val = self.pop_value_from_stack(opcode)?;
                 self.set_register_value(reg, val);
             }
 
             Render => {
-                self.display.render_current(
+                self.display.render(
                     &self.memory[DISPLAY_MEMORY_START..DISPLAY_MEMORY_END]
                 );
             },
 
             Wait(secs) => {
-                std::thread::sleep(std::time::Duration::from_secs(secs as u64));
+                self.pending_wait = Some(std::time::Duration::from_secs(secs as u64));
             },
 
             Jump(hi, lo) => {
-                let hi = (hi as usize) << 8;
-                let address: usize = hi + lo as usize;
-                self.program_counter = address;
-                self.pc_manually_set = true;
+                self.jump_if(opcode, true, hi, lo)?;
             },
             JumpIfZero(hi, lo) => {
-                if self.zero_flag {
-                    let hi = (hi as usize) << 8;
-                    let address: usize = hi + lo as usize;
-                    self.program_counter = address;
-                    self.pc_manually_set = true;
-                }
+                self.jump_if(opcode, self.flags.zero, hi, lo)?;
+            },
+            JumpIfNotZero(hi, lo) => {
+                self.jump_if(opcode, !self.flags.zero, hi, lo)?;
+            },
+            JumpIfCarry(hi, lo) => {
+                self.jump_if(opcode, self.flags.carry, hi, lo)?;
+            },
+            JumpIfNotCarry(hi, lo) => {
+                self.jump_if(opcode, !self.flags.carry, hi, lo)?;
+            },
+            JumpIfNegative(hi, lo) => {
+                self.jump_if(opcode, self.flags.negative, hi, lo)?;
+            },
+            JumpIfGreater(hi, lo) => {
+                self.jump_if(opcode, self.flags.greater(), hi, lo)?;
+            },
+            JumpIfLess(hi, lo) => {
+                self.jump_if(opcode, self.flags.less(), hi, lo)?;
+            },
+            JumpIfGreaterOrEqual(hi, lo) => {
+                self.jump_if(opcode, !self.flags.less(), hi, lo)?;
+            },
+            JumpIfLessOrEqual(hi, lo) => {
+                self.jump_if(opcode, !self.flags.greater(), hi, lo)?;
             },
 
             Call(hi, lo) => {
@@ -244,26 +817,24 @@ impl RocCPURunner {
 
                 // First, push current PC to stack
                 // (Bottom of stack) [ .., lobytes, hibytes, .. ] (Top of Stack)
-                let to_return_to = self.program_counter + 1;
+                let to_return_to = self.next_program_counter;
                 let pc_lo = to_return_to as u8;
                 let pc_hi = (to_return_to >> 8) as u8;
-                self.push_value_to_stack(pc_lo);
-                self.push_value_to_stack(pc_hi);
+                self.push_value_to_stack(opcode, pc_lo)?;
+                self.push_value_to_stack(opcode, pc_hi)?;
 
-                self.program_counter = address;
-                self.pc_manually_set = true;
+                self.jump_to(opcode, address)?;
             },
 
             Return => {
-                let hi = self.pop_value_from_stack();
-                let lo = self.pop_value_from_stack();
+                let hi = self.pop_value_from_stack(opcode)?;
+                let lo = self.pop_value_from_stack(opcode)?;
                 let address = ((hi as usize) << 8) + lo as usize;
-                self.program_counter = address;
-                self.pc_manually_set = true;
+                self.jump_to(opcode, address)?;
             }
-
-            _ => {}
         }
+
+        Ok(())
     }
 
 }
@@ -273,12 +844,21 @@ impl RocCPURunner {
 impl RocCPURunner {
 
     fn reset_execution_stuff(&mut self) {
-        self.program_counter = 0;
+        self.program_counter = match self.execution_mode {
+            RocCPUExecutionMode::InstructionList => 0,
+            RocCPUExecutionMode::Memory { entry_point } => entry_point as usize,
+        };
         self.should_continue = true;
         self.stack = [0; 0xFF];
+        self.stack_pointer = 0;
+        self.pc_manually_set = false;
+        self.pending_wait = None;
+        self.fault = None;
+        self.memory_watch_hits.clear();
+        self.watchpoint_hits.clear();
     }
 
-    fn get_register_idx(&self, register: RocCPURegister) -> usize {
+    pub(crate) fn get_register_idx(register: RocCPURegister) -> usize {
         use crate::RocCPURegister::*;
         
         match register {
@@ -297,12 +877,127 @@ impl RocCPURunner {
     }
 
     fn get_register_value(&self, register: RocCPURegister) -> u8 {
-        let idx = self.get_register_idx(register);
+        let idx = Self::get_register_idx(register);
         self.registers[idx]
     }
 
     fn set_register_value(&mut self, register: RocCPURegister, value: u8) {
-        let idx = self.get_register_idx(register);
+        let idx = Self::get_register_idx(register);
         self.registers[idx] = value;
     }
+
+    /// Reads two registers as one 16-bit value,
+    /// with `hi` holding the top byte.
+    fn get_register_pair(&self, hi: RocCPURegister, lo: RocCPURegister) -> u16 {
+        u16::from_be_bytes([self.get_register_value(hi), self.get_register_value(lo)])
+    }
+
+    fn set_register_pair(&mut self, hi: RocCPURegister, lo: RocCPURegister, value: u16) {
+        let [hi_val, lo_val] = value.to_be_bytes();
+        self.set_register_value(hi, hi_val);
+        self.set_register_value(lo, lo_val);
+    }
+
+    fn copy_into_memory(&mut self, section: &RocBinarySection) {
+        let start = section.load_address as usize;
+        let end = (start + section.bytes.len()).min(self.memory.len());
+        self.memory[start..end].copy_from_slice(&section.bytes[..end - start]);
+    }
+
+    fn read_memory(&mut self, address: u16) -> u8 {
+        let value = self.memory[address as usize];
+        self.watch_memory(RocCPUWatchpointEvent::MemoryRead { address, value });
+        value
+    }
+
+    fn write_memory(&mut self, address: u16, value: u8) {
+        self.watch_memory(RocCPUWatchpointEvent::MemoryWrite {
+            address,
+            old: self.memory[address as usize],
+            new: value,
+        });
+        if let Some(entry) = &mut self.trace_entry {
+            entry.memory.push(RocCPUMemoryWrite {
+                address,
+                old: self.memory[address as usize],
+                new: value,
+            });
+        }
+        self.memory[address as usize] = value;
+    }
+
+    /// Works out which registers and flags the instruction
+    /// changed, and hands the finished entry to the tracer.
+    fn finish_trace_entry(&mut self, registers_before: [u8; 10], flags_before: RocCPUFlags) {
+        let Some(mut entry) = self.trace_entry.take() else {
+            return;
+        };
+
+        for register in RocCPURegister::ALL {
+            let idx = Self::get_register_idx(register);
+            let (old, new) = (registers_before[idx], self.registers[idx]);
+            if old != new {
+                entry.registers.push(RocCPURegisterChange { register, old, new });
+            }
+        }
+        if flags_before != self.flags {
+            entry.flags = Some(RocCPUFlagsChange { old: flags_before, new: self.flags });
+        }
+
+        if let Some(tracer) = &mut self.tracer {
+            tracer.record(&entry);
+        }
+        self.traced_steps += 1;
+    }
+
+    fn watch_memory(&mut self, event: RocCPUWatchpointEvent) {
+        for (id, watchpoint) in &self.watchpoints {
+            if watchpoint.is_hit_by(&event) {
+                self.memory_watch_hits.push((*id, event));
+            }
+        }
+    }
+
+    /// Turns the memory accesses that set off watchpoints
+    /// into hits, then compares registers and the stack
+    /// pointer with how they were before the instruction.
+    fn check_watchpoints(
+        &mut self,
+        pc: usize,
+        instruction: RocCPUInstruction,
+        registers_before: [u8; 10],
+        stack_pointer_before: usize
+    ) {
+        if self.watchpoints.is_empty() {
+            return;
+        }
+
+        let mut events: Vec<_> = RocCPURegister::ALL.iter()
+            .map(|register| {
+                let idx = Self::get_register_idx(*register);
+                RocCPUWatchpointEvent::Register {
+                    register: *register,
+                    old: registers_before[idx],
+                    new: self.registers[idx],
+                }
+            })
+            .collect();
+        events.push(RocCPUWatchpointEvent::StackPointer {
+            old: stack_pointer_before,
+            new: self.stack_pointer,
+        });
+
+        let mut hits = std::mem::take(&mut self.memory_watch_hits);
+        for event in events {
+            for (id, watchpoint) in &self.watchpoints {
+                if watchpoint.is_hit_by(&event) {
+                    hits.push((*id, event));
+                }
+            }
+        }
+
+        self.watchpoint_hits = hits.into_iter()
+            .map(|(id, event)| RocCPUWatchpointHit { id, pc, instruction, event })
+            .collect();
+    }
 }
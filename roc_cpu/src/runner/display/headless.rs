@@ -0,0 +1,69 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use super::*;
+
+/// A display that throws every frame away. Useful
+/// for running programs without a window.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RocCPUNullDisplay;
+
+impl RocCPUDisplayBackend for RocCPUNullDisplay {
+    fn render(&mut self, _vmem: &[u8]) {}
+}
+
+
+/// A display that keeps the most recently rendered
+/// frame in memory, so it can be inspected afterwards.
+///
+/// Clones share the same framebuffer, so keep a clone
+/// around after handing one to a `RocCPURunner`.
+#[derive(Clone, Debug)]
+pub struct RocCPUFramebufferDisplay {
+    frame: Rc<RefCell<Vec<u8>>>,
+    frames_rendered: Rc<Cell<usize>>,
+}
+
+impl Default for RocCPUFramebufferDisplay {
+    fn default() -> Self {
+        Self {
+            frame: Rc::new(RefCell::new(vec![0; DISPLAY_MEMORY_USAGE as usize])),
+            frames_rendered: Rc::new(Cell::new(0)),
+        }
+    }
+}
+
+impl RocCPUFramebufferDisplay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A copy of the most recently rendered frame.
+    pub fn frame(&self) -> Vec<u8> {
+        self.frame.borrow().clone()
+    }
+
+    /// The `(r, g, b)` colour of one pixel in the most
+    /// recently rendered frame.
+    pub fn pixel(&self, x: u32, y: u32) -> Option<(u8, u8, u8)> {
+        if x >= DISPLAY_WIDTH || y >= DISPLAY_HEIGHT {
+            return None;
+        }
+
+        let idx = pixel_offset(x, y);
+        let frame = self.frame.borrow();
+        Some((frame[idx], frame[idx + 1], frame[idx + 2]))
+    }
+
+    /// How many times `Render` has been executed.
+    pub fn frames_rendered(&self) -> usize {
+        self.frames_rendered.get()
+    }
+}
+
+impl RocCPUDisplayBackend for RocCPUFramebufferDisplay {
+    fn render(&mut self, vmem: &[u8]) {
+        self.frame.borrow_mut().copy_from_slice(vmem);
+        self.frames_rendered.set(self.frames_rendered.get() + 1);
+    }
+}
@@ -0,0 +1,31 @@
+#[cfg(feature = "sdl")]
+mod sdl;
+mod headless;
+
+#[cfg(feature = "sdl")]
+pub use sdl::RocCPUSdlDisplay;
+pub use headless::*;
+
+pub const DISPLAY_WIDTH: u32 = 40;
+pub const DISPLAY_HEIGHT: u32 = 32;
+
+pub const DISPLAY_MEMORY_USAGE: u32 = 3 * DISPLAY_WIDTH * DISPLAY_HEIGHT;
+pub const DISPLAY_MEMORY_START: usize = 0x8000;
+pub const DISPLAY_MEMORY_END: usize = DISPLAY_MEMORY_START + DISPLAY_MEMORY_USAGE as usize;
+
+/// Somewhere for the `Render` instruction to send
+/// video memory.
+///
+/// Video memory is laid out column by column, with
+/// three bytes (red, green, blue) per pixel, so the
+/// pixel at `(x, y)` starts at `3 * (x * DISPLAY_HEIGHT + y)`.
+pub trait RocCPUDisplayBackend {
+    /// Shows one frame. `vmem` is exactly
+    /// `DISPLAY_MEMORY_USAGE` bytes long.
+    fn render(&mut self, vmem: &[u8]);
+}
+
+/// The byte offset into video memory of the pixel at `(x, y)`.
+pub fn pixel_offset(x: u32, y: u32) -> usize {
+    (x * 3 * DISPLAY_HEIGHT + y * 3) as usize
+}
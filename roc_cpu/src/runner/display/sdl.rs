@@ -1,15 +1,13 @@
 use sdl3::{pixels::Color, rect::Rect, *};
 
-pub const DISPLAY_WIDTH: u32 = 40;
-pub const DISPLAY_HEIGHT: u32 = 32;
-const DISPLAY_SCALE: u32 = 20;
+use super::*;
 
-pub const DISPLAY_MEMORY_USAGE: u32 = 3 * DISPLAY_WIDTH * DISPLAY_HEIGHT;
-pub const DISPLAY_MEMORY_START: usize = 0x8000;
-pub const DISPLAY_MEMORY_END: usize = DISPLAY_MEMORY_START + DISPLAY_MEMORY_USAGE as usize;
+const DISPLAY_SCALE: u32 = 20;
 
+/// Draws video memory into an SDL window. Creating one
+/// panics if SDL cannot open the window.
 #[allow(dead_code)]
-pub struct RocCPUDisplay {
+pub struct RocCPUSdlDisplay {
     sdl: Sdl,
     video_subsystem: VideoSubsystem,
     window: video::Window,
@@ -18,7 +16,7 @@ pub struct RocCPUDisplay {
 
 // User-level API
 
-impl RocCPUDisplay {
+impl RocCPUSdlDisplay {
     pub fn new() -> Self {
         
         let sdl = match sdl3::init() {
@@ -54,7 +52,16 @@ impl RocCPUDisplay {
         }
     }
 
-    pub fn render_current(&mut self, vmem: &[u8]) {
+}
+
+impl Default for RocCPUSdlDisplay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RocCPUDisplayBackend for RocCPUSdlDisplay {
+    fn render(&mut self, vmem: &[u8]) {
         for event in self.event_pump.poll_iter() {
             match event {
                 _ => {}
@@ -67,7 +74,7 @@ impl RocCPUDisplay {
 
 // Private Methods
 
-impl RocCPUDisplay {
+impl RocCPUSdlDisplay {
     fn copy_vmem_to_window(&mut self, vmem: &[u8]) {
         let mut window_surface = match self.window.surface(&self.event_pump) {
             Ok(surf) => surf,
@@ -78,7 +85,7 @@ impl RocCPUDisplay {
 
         for i in 0..DISPLAY_WIDTH {
             for j in 0..DISPLAY_HEIGHT {
-                let idx = (i * 3 * DISPLAY_HEIGHT + j * 3) as usize;
+                let idx = pixel_offset(i, j);
                 let color = Color::RGB(
                     vmem[idx],
                     vmem[idx + 1],
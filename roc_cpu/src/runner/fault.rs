@@ -0,0 +1,84 @@
+use std::fmt;
+
+use roc_cpu_types::*;
+
+/// Something a program did that the CPU cannot carry
+/// on from. `pc` is the program counter of the
+/// instruction that faulted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RocCPUFault {
+    /// Pushed onto a full stack.
+    StackOverflow { pc: usize, instruction: RocCPUInstruction },
+
+    /// Popped from an empty stack.
+    StackUnderflow { pc: usize, instruction: RocCPUInstruction },
+
+    /// Jumped, called or returned to an address
+    /// outside the program.
+    InvalidJumpTarget { pc: usize, instruction: RocCPUInstruction, target: usize },
+
+    /// Divided by zero.
+    DivideByZero { pc: usize, instruction: RocCPUInstruction },
+
+    /// Fetched a byte from memory that isn't a known
+    /// instruction, or isn't a register where the
+    /// instruction needs one.
+    UnknownOpcode { pc: usize, opcode: u8 },
+
+    /// Ran off the end of the program without
+    /// reaching an `Exit`.
+    ProgramCounterOutOfBounds { pc: usize },
+}
+
+impl RocCPUFault {
+    /// The program counter of the faulting instruction.
+    pub fn pc(&self) -> usize {
+        match self {
+            Self::StackOverflow { pc, .. }
+            | Self::StackUnderflow { pc, .. }
+            | Self::InvalidJumpTarget { pc, .. }
+            | Self::DivideByZero { pc, .. }
+            | Self::UnknownOpcode { pc, .. }
+            | Self::ProgramCounterOutOfBounds { pc } => *pc,
+        }
+    }
+
+    /// The faulting instruction, when there was one.
+    pub fn instruction(&self) -> Option<RocCPUInstruction> {
+        match self {
+            Self::StackOverflow { instruction, .. }
+            | Self::StackUnderflow { instruction, .. }
+            | Self::InvalidJumpTarget { instruction, .. }
+            | Self::DivideByZero { instruction, .. } => Some(*instruction),
+            Self::UnknownOpcode { .. }
+            | Self::ProgramCounterOutOfBounds { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for RocCPUFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StackOverflow { pc, instruction } => {
+                write!(f, "stack overflow at {:#06x} ({:?})", pc, instruction)
+            },
+            Self::StackUnderflow { pc, instruction } => {
+                write!(f, "stack underflow at {:#06x} ({:?})", pc, instruction)
+            },
+            Self::InvalidJumpTarget { pc, instruction, target } => {
+                write!(f, "invalid jump target {:#06x} at {:#06x} ({:?})", target, pc, instruction)
+            },
+            Self::DivideByZero { pc, instruction } => {
+                write!(f, "divide by zero at {:#06x} ({:?})", pc, instruction)
+            },
+            Self::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:#04x} at {:#06x}", opcode, pc)
+            },
+            Self::ProgramCounterOutOfBounds { pc } => {
+                write!(f, "program counter {:#06x} ran past the end of the program", pc)
+            },
+        }
+    }
+}
+
+impl std::error::Error for RocCPUFault {}
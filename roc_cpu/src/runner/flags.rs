@@ -0,0 +1,58 @@
+/// The status flags, as set by the most recent
+/// arithmetic or comparison instruction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RocCPUFlags {
+    /// The result was zero.
+    pub zero: bool,
+
+    /// The unsigned result did not fit in a byte. For
+    /// subtraction (and `Cmp`) this is the borrow, so it
+    /// is set when the left operand was smaller.
+    pub carry: bool,
+
+    /// The top bit of the result was set.
+    pub negative: bool,
+
+    /// The result, read as a signed (two's complement)
+    /// value, did not fit in a byte.
+    pub overflow: bool,
+}
+
+impl RocCPUFlags {
+    pub(crate) fn from_result(result: u8, carry: bool, overflow: bool) -> Self {
+        Self {
+            zero: result == 0,
+            carry,
+            negative: result & 0x80 != 0,
+            overflow,
+        }
+    }
+
+    /// Zero, carry, negative and overflow in bits 0 to 3,
+    /// as GDB and binary traces store them.
+    pub(crate) fn to_bits(self) -> u8 {
+        self.zero as u8
+            | (self.carry as u8) << 1
+            | (self.negative as u8) << 2
+            | (self.overflow as u8) << 3
+    }
+
+    pub(crate) fn from_bits(bits: u8) -> Self {
+        Self {
+            zero: bits & 1 != 0,
+            carry: bits & 2 != 0,
+            negative: bits & 4 != 0,
+            overflow: bits & 8 != 0,
+        }
+    }
+
+    /// Unsigned "greater than", as left by `Cmp(a, b)`.
+    pub fn greater(&self) -> bool {
+        !self.carry && !self.zero
+    }
+
+    /// Unsigned "less than", as left by `Cmp(a, b)`.
+    pub fn less(&self) -> bool {
+        self.carry
+    }
+}
@@ -1,4 +1,29 @@
 mod cpu;
 mod display;
+mod fault;
+mod flags;
+mod mode;
+mod outcome;
+mod state;
+mod watchpoint;
 
-pub use cpu::RocCPURunner;
\ No newline at end of file
+#[cfg(test)]
+mod tests;
+
+pub use cpu::RocCPURunner;
+pub use flags::RocCPUFlags;
+pub use mode::RocCPUExecutionMode;
+pub use outcome::RocCPUStepOutcome;
+pub use fault::RocCPUFault;
+pub use state::RocCPUState;
+pub use watchpoint::{RocCPUWatchpoint, RocCPUWatchpointEvent, RocCPUWatchpointHit};
+pub use display::{
+    RocCPUDisplayBackend,
+    RocCPUNullDisplay,
+    RocCPUFramebufferDisplay,
+    DISPLAY_WIDTH,
+    DISPLAY_HEIGHT,
+    DISPLAY_MEMORY_START,
+};
+#[cfg(feature = "sdl")]
+pub use display::RocCPUSdlDisplay;
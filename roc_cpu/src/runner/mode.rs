@@ -0,0 +1,16 @@
+/// Where a `RocCPURunner` fetches its instructions from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RocCPUExecutionMode {
+    /// Instructions come from the program passed to
+    /// `load_program`, separate from memory. The program
+    /// counter, and every jump target, is an index into
+    /// that list of instructions.
+    #[default]
+    InstructionList,
+
+    /// Instructions are decoded from `memory`, starting at
+    /// `entry_point`. The program counter, and every jump
+    /// target, is a byte address. Programs can read and
+    /// write their own code.
+    Memory { entry_point: u16 },
+}
@@ -0,0 +1,23 @@
+use std::time::Duration;
+
+use crate::runner::fault::RocCPUFault;
+
+/// What happened when a `RocCPURunner` executed
+/// an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RocCPUStepOutcome {
+    /// The instruction ran, and there is more to execute.
+    Continued,
+
+    /// The program has finished, either by executing
+    /// `Exit` or because there is no program loaded.
+    Exited,
+
+    /// The program faulted. Stepping again reports
+    /// the same fault until the runner is reset.
+    Faulted(RocCPUFault),
+
+    /// A `Wait` instruction ran. The program should be
+    /// paused for this long before it is stepped again.
+    Waiting(Duration),
+}
@@ -0,0 +1,24 @@
+use roc_cpu_types::*;
+use crate::runner::RocCPURunner;
+use crate::runner::flags::RocCPUFlags;
+
+/// A snapshot of a `RocCPURunner`'s registers,
+/// flags and pointers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RocCPUState {
+    pub registers: [u8; 10],
+    pub flags: RocCPUFlags,
+    pub program_counter: usize,
+    pub stack_pointer: usize,
+}
+
+impl RocCPUState {
+    pub fn register(&self, register: RocCPURegister) -> u8 {
+        self.registers[RocCPURunner::get_register_idx(register)]
+    }
+
+    /// The program's exit code, from `$ret`.
+    pub fn return_value(&self) -> u8 {
+        self.register(RocCPURegister::ReturnValue)
+    }
+}
@@ -0,0 +1,295 @@
+use std::time::Duration;
+
+use roc_cpu_types::*;
+
+use super::*;
+
+use RocCPURegister::*;
+
+/// A runner with `source` loaded as an instruction list.
+fn runner(source: &str) -> RocCPURunner {
+    let program = crate::asm::assemble(source).unwrap();
+    let mut runner = RocCPURunner::default();
+    runner.load_program(&program);
+    runner
+}
+
+
+#[test]
+fn steps_continue_until_exit() {
+    let mut runner = runner("PUT $ax, 1; EXIT;");
+    assert_eq!(runner.step(), RocCPUStepOutcome::Continued);
+    assert_eq!(runner.register(GeneralPurposeA), 1);
+    assert_eq!(runner.step(), RocCPUStepOutcome::Exited);
+
+    // Stays exited.
+    assert_eq!(runner.step(), RocCPUStepOutcome::Exited);
+    assert_eq!(runner.program_counter(), 1);
+}
+
+#[test]
+fn steps_without_a_program_exit() {
+    assert_eq!(RocCPURunner::default().step(), RocCPUStepOutcome::Exited);
+}
+
+#[test]
+fn waits_are_handed_back() {
+    let mut runner = runner("WAIT 2; EXIT;");
+    assert_eq!(runner.step(), RocCPUStepOutcome::Waiting(Duration::from_secs(2)));
+    assert_eq!(runner.step(), RocCPUStepOutcome::Exited);
+}
+
+#[test]
+fn faults_stick_until_reset() {
+    let mut runner = runner("POP $ax; EXIT;");
+    let fault = RocCPUFault::StackUnderflow { pc: 0, instruction: RocCPUInstruction::Pop(GeneralPurposeA) };
+    assert_eq!(runner.step(), RocCPUStepOutcome::Faulted(fault));
+    assert_eq!(runner.step(), RocCPUStepOutcome::Faulted(fault));
+    assert_eq!(runner.fault(), Some(fault));
+    assert_eq!(runner.program_counter(), 0);
+
+    runner.reset();
+    assert_eq!(runner.fault(), None);
+    assert_eq!(runner.step(), RocCPUStepOutcome::Faulted(fault));
+}
+
+#[test]
+fn run_for_runs_exactly_that_many_instructions() {
+    let mut runner = runner("@loop ADDI $ax, 1; JUMP @loop;");
+    assert_eq!(runner.run_for(5), RocCPUStepOutcome::Continued);
+    assert_eq!(runner.register(GeneralPurposeA), 3);
+    assert_eq!(runner.program_counter(), 1);
+
+    assert_eq!(runner.run_for(0), RocCPUStepOutcome::Continued);
+    assert_eq!(runner.program_counter(), 1);
+}
+
+#[test]
+fn run_for_stops_early_on_exit() {
+    let mut runner = runner("NOP; EXIT; PUT $ax, 1;");
+    assert_eq!(runner.run_for(10), RocCPUStepOutcome::Exited);
+    assert_eq!(runner.register(GeneralPurposeA), 0);
+}
+
+#[test]
+fn run_until_stops_before_the_instruction_the_predicate_matches() {
+    let mut runner = runner("@loop ADDI $ax, 1; JUMP @loop;");
+    let outcome = runner.run_until(|runner| runner.register(GeneralPurposeA) == 4);
+    assert_eq!(outcome, RocCPUStepOutcome::Continued);
+    assert_eq!(runner.register(GeneralPurposeA), 4);
+    assert_eq!(runner.program_counter(), 1);
+
+    // Already true, so nothing runs.
+    assert_eq!(runner.run_until(|_| true), RocCPUStepOutcome::Continued);
+    assert_eq!(runner.program_counter(), 1);
+}
+
+#[test]
+fn run_until_stops_early_on_a_fault() {
+    let mut runner = runner("NOP; DIVI $ax, 0; EXIT;");
+    let outcome = runner.run_until(|_| false);
+    let fault = RocCPUFault::DivideByZero { pc: 1, instruction: RocCPUInstruction::DivI(GeneralPurposeA, 0) };
+    assert_eq!(outcome, RocCPUStepOutcome::Faulted(fault));
+}
+
+#[test]
+fn running_off_the_end_faults() {
+    // Programs used to stop here with 255 in $ret.
+    let mut runner = runner("PUT $ax, 1;");
+    assert_eq!(runner.step(), RocCPUStepOutcome::Continued);
+    let fault = RocCPUFault::ProgramCounterOutOfBounds { pc: 1 };
+    assert_eq!(runner.step(), RocCPUStepOutcome::Faulted(fault));
+    assert_eq!(runner.execute(), Err(fault));
+    assert_eq!(runner.register(ReturnValue), 0);
+}
+
+
+/// Runs `op $ax, $bx` with `$ax = a` and `$bx = b`,
+/// returning `$ax` and the flags as `ZCNV`.
+fn alu(op: &str, a: u8, b: u8) -> (u8, String) {
+    let mut runner = runner(&format!("PUT $ax, {}; PUT $bx, {}; {} $ax, $bx; EXIT;", a, b, op));
+    assert_eq!(runner.run_for(3), RocCPUStepOutcome::Continued, "{} {} {}", op, a, b);
+    (runner.register(GeneralPurposeA), crate::trace::flags_text(runner.flags()))
+}
+
+#[test]
+fn arithmetic_sets_every_flag() {
+    let cases = [
+        ("ADD", 1, 2, 3, "----"),
+        ("ADD", 0, 0, 0, "Z---"),
+        ("ADD", 0xFF, 1, 0, "ZC--"),
+        ("ADD", 0x7F, 1, 0x80, "--NV"),
+        ("ADD", 0x80, 0x80, 0, "ZC-V"),
+        ("ADD", 0xFF, 0xFF, 0xFE, "-CN-"),
+
+        ("SUB", 5, 3, 2, "----"),
+        ("SUB", 5, 5, 0, "Z---"),
+        ("SUB", 3, 5, 0xFE, "-CN-"),
+        ("SUB", 0x80, 1, 0x7F, "---V"),
+        ("SUB", 0x7F, 0xFF, 0x80, "-CNV"),
+
+        ("MUL", 3, 4, 12, "----"),
+        ("MUL", 5, 0, 0, "Z---"),
+        ("MUL", 16, 16, 0, "ZC-V"),
+        ("MUL", 0x40, 2, 0x80, "--NV"),
+        ("MUL", 0xFF, 0xFF, 1, "-C--"),
+
+        ("DIV", 7, 2, 3, "----"),
+        ("DIV", 0, 5, 0, "Z---"),
+        ("DIV", 0xFF, 1, 0xFF, "--N-"),
+    ];
+
+    for (op, a, b, result, flags) in cases {
+        assert_eq!(alu(op, a, b), (result, flags.to_string()), "{} {}, {}", op, a, b);
+    }
+}
+
+#[test]
+fn compares_set_flags_without_changing_registers() {
+    let (value, flags) = alu("CMP", 3, 5);
+    assert_eq!((value, flags.as_str()), (3, "-CN-"));
+}
+
+#[test]
+fn greater_and_less_are_unsigned() {
+    // (a, b, greater, less) after `CMP a, b`.
+    let cases = [
+        (200, 100, true, false),
+        (100, 200, false, true),
+        (0x80, 0x7F, true, false),
+        (0x7F, 0x80, false, true),
+        (5, 5, false, false),
+        (0, 0xFF, false, true),
+    ];
+
+    for (a, b, greater, less) in cases {
+        let mut runner = runner(&format!("PUT $ax, {}; PUT $bx, {}; CMP $ax, $bx; EXIT;", a, b));
+        runner.run_for(3);
+        assert_eq!((runner.flags().greater(), runner.flags().less()), (greater, less), "CMP {}, {}", a, b);
+    }
+}
+
+#[test]
+fn conditional_jumps_use_unsigned_comparisons() {
+    let source = "PUT $ax, 200; PUT $bx, 100; CMP $ax, $bx; JGT @greater; SETRET 0; EXIT; @greater SETRET 1; EXIT;";
+    let mut runner = runner(source);
+    assert_eq!(runner.execute().map(|state| state.return_value()), Ok(1));
+}
+
+#[test]
+fn dividing_by_zero_faults() {
+    let mut runner = runner("PUT $ax, 7; DIV $ax, $bx; EXIT;");
+    let fault = RocCPUFault::DivideByZero {
+        pc: 1,
+        instruction: RocCPUInstruction::Div(GeneralPurposeA, GeneralPurposeB),
+    };
+    assert_eq!(runner.run_for(2), RocCPUStepOutcome::Faulted(fault));
+    assert_eq!(runner.register(GeneralPurposeA), 7);
+}
+
+#[test]
+fn pushing_onto_a_full_stack_overflows() {
+    let mut runner = runner("@loop PUSH $ax; JUMP @loop;");
+    let fault = RocCPUFault::StackOverflow { pc: 0, instruction: RocCPUInstruction::Push(GeneralPurposeA) };
+    assert_eq!(runner.run_until(|_| false), RocCPUStepOutcome::Faulted(fault));
+    assert_eq!(runner.stack_pointer(), 0xFF);
+}
+
+#[test]
+fn calling_with_a_full_stack_overflows() {
+    let mut runner = runner("@f CALL @f;");
+    let outcome = runner.run_until(|_| false);
+    assert!(matches!(outcome, RocCPUStepOutcome::Faulted(RocCPUFault::StackOverflow { pc: 0, .. })), "{:?}", outcome);
+}
+
+#[test]
+fn returning_with_an_empty_stack_underflows() {
+    let mut runner = runner("RETURN;");
+    let fault = RocCPUFault::StackUnderflow { pc: 0, instruction: RocCPUInstruction::Return };
+    assert_eq!(runner.step(), RocCPUStepOutcome::Faulted(fault));
+}
+
+
+const WATCHED: &str = "PUT $ax, 9; STORE 0x4000, $ax; LOAD $bx, 0x4001; EXIT;";
+
+/// Steps `runner` until a watchpoint is hit, returning
+/// the outcome and the hits.
+fn run_to_watchpoint(runner: &mut RocCPURunner) -> (RocCPUStepOutcome, Vec<RocCPUWatchpointHit>) {
+    loop {
+        let outcome = runner.step();
+        if outcome != RocCPUStepOutcome::Continued || !runner.watchpoint_hits().is_empty() {
+            return (outcome, runner.watchpoint_hits().to_vec());
+        }
+    }
+}
+
+#[test]
+fn stores_to_watched_addresses_are_hits() {
+    let mut runner = runner(WATCHED);
+    let id = runner.add_watchpoint(RocCPUWatchpoint::MemoryWrite(0x4000..=0x4000));
+
+    let (outcome, hits) = run_to_watchpoint(&mut runner);
+    assert_eq!(outcome, RocCPUStepOutcome::Continued);
+    assert_eq!(hits, [RocCPUWatchpointHit {
+        id,
+        pc: 1,
+        instruction: RocCPUInstruction::Store(0x40, 0x00, GeneralPurposeA),
+        event: RocCPUWatchpointEvent::MemoryWrite { address: 0x4000, old: 0, new: 9 },
+    }]);
+
+    // The store still happened, and hits only last a step.
+    assert_eq!(runner.memory()[0x4000], 9);
+    assert_eq!(runner.step(), RocCPUStepOutcome::Continued);
+    assert!(runner.watchpoint_hits().is_empty());
+}
+
+#[test]
+fn unwatched_addresses_are_not_hits() {
+    let mut runner = runner(WATCHED);
+    runner.add_watchpoint(RocCPUWatchpoint::MemoryWrite(0x4001..=0x40FF));
+    runner.add_watchpoint(RocCPUWatchpoint::MemoryRead(0x4000..=0x4000));
+    runner.add_watchpoint(RocCPUWatchpoint::MemoryAccess(0x3000..=0x3FFF));
+
+    assert_eq!(run_to_watchpoint(&mut runner), (RocCPUStepOutcome::Exited, vec![]));
+}
+
+#[test]
+fn reads_of_watched_addresses_are_hits() {
+    let mut runner = runner(WATCHED);
+    let id = runner.add_watchpoint(RocCPUWatchpoint::MemoryRead(0x4000..=0x4001));
+
+    let (_, hits) = run_to_watchpoint(&mut runner);
+    assert_eq!(hits.len(), 1);
+    assert_eq!((hits[0].id, hits[0].pc), (id, 2));
+    assert_eq!(hits[0].event, RocCPUWatchpointEvent::MemoryRead { address: 0x4001, value: 0 });
+}
+
+#[test]
+fn accesses_watch_both_reads_and_writes() {
+    let mut runner = runner(WATCHED);
+    runner.add_watchpoint(RocCPUWatchpoint::MemoryAccess(0x4000..=0x4001));
+
+    let (_, hits) = run_to_watchpoint(&mut runner);
+    assert_eq!(hits[0].pc, 1);
+    let (_, hits) = run_to_watchpoint(&mut runner);
+    assert_eq!(hits[0].pc, 2);
+    assert_eq!(run_to_watchpoint(&mut runner), (RocCPUStepOutcome::Exited, vec![]));
+}
+
+#[test]
+fn removed_watchpoints_are_not_hits() {
+    let mut runner = runner(WATCHED);
+    let id = runner.add_watchpoint(RocCPUWatchpoint::MemoryWrite(0x4000..=0x4000));
+    assert!(runner.remove_watchpoint(id));
+    assert_eq!(run_to_watchpoint(&mut runner), (RocCPUStepOutcome::Exited, vec![]));
+}
+
+#[test]
+fn register_watchpoints_are_hit_when_the_register_changes() {
+    let mut runner = runner("PUT $ax, 0; PUT $ax, 9; EXIT;");
+    runner.add_watchpoint(RocCPUWatchpoint::RegisterChanged(GeneralPurposeA));
+
+    let (_, hits) = run_to_watchpoint(&mut runner);
+    assert_eq!(hits[0].pc, 1);
+    assert_eq!(hits[0].event, RocCPUWatchpointEvent::Register { register: GeneralPurposeA, old: 0, new: 9 });
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use roc_cpu_types::*;
+
+/// Something for a `RocCPURunner` to watch for, see
+/// `RocCPURunner::add_watchpoint`.
+///
+/// Only instructions reading and writing memory count as
+/// accesses: fetching instructions and `Render` don't.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RocCPUWatchpoint {
+    /// Any byte in the range being read.
+    MemoryRead(RangeInclusive<u16>),
+
+    /// Any byte in the range being written, even
+    /// with the value it already had.
+    MemoryWrite(RangeInclusive<u16>),
+
+    /// Any byte in the range being read or written.
+    MemoryAccess(RangeInclusive<u16>),
+
+    /// The register's value changing.
+    RegisterChanged(RocCPURegister),
+
+    /// The register changing to the given value.
+    RegisterEquals(RocCPURegister, u8),
+
+    /// The stack pointer going from at or below the
+    /// threshold to above it.
+    StackPointerAbove(usize),
+
+    /// The stack pointer going from at or above the
+    /// threshold to below it.
+    StackPointerBelow(usize),
+}
+
+/// What an instruction did to set off a watchpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RocCPUWatchpointEvent {
+    MemoryRead { address: u16, value: u8 },
+    MemoryWrite { address: u16, old: u8, new: u8 },
+    Register { register: RocCPURegister, old: u8, new: u8 },
+    StackPointer { old: usize, new: usize },
+}
+
+/// A watchpoint that the most recent instruction set off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RocCPUWatchpointHit {
+    /// As returned by `RocCPURunner::add_watchpoint`.
+    pub id: usize,
+
+    /// Where the instruction was, in the same terms as
+    /// `RocCPURunner::program_counter`.
+    pub pc: usize,
+    pub instruction: RocCPUInstruction,
+    pub event: RocCPUWatchpointEvent,
+}
+
+impl RocCPUWatchpoint {
+    /// Whether `event`, which happened part way through an
+    /// instruction, sets this off. Registers and the stack
+    /// pointer are only compared once it's finished.
+    pub(crate) fn is_hit_by(&self, event: &RocCPUWatchpointEvent) -> bool {
+        use RocCPUWatchpointEvent::*;
+
+        match (self, event) {
+            (Self::MemoryRead(range) | Self::MemoryAccess(range), MemoryRead { address, .. }) => {
+                range.contains(address)
+            },
+            (Self::MemoryWrite(range) | Self::MemoryAccess(range), MemoryWrite { address, .. }) => {
+                range.contains(address)
+            },
+            (Self::RegisterChanged(watched), Register { register, old, new }) => {
+                watched == register && old != new
+            },
+            (Self::RegisterEquals(watched, value), Register { register, old, new }) => {
+                watched == register && old != new && new == value
+            },
+            (Self::StackPointerAbove(threshold), StackPointer { old, new }) => {
+                old <= threshold && new > threshold
+            },
+            (Self::StackPointerBelow(threshold), StackPointer { old, new }) => {
+                old >= threshold && new < threshold
+            },
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for RocCPUWatchpointEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MemoryRead { address, value } => {
+                write!(f, "read {:#04x} from {:#06x}", value, address)
+            },
+            Self::MemoryWrite { address, old, new } => {
+                write!(f, "wrote {:#06x}: {:#04x} -> {:#04x}", address, old, new)
+            },
+            Self::Register { register, old, new } => {
+                write!(f, "{}: {:#04x} -> {:#04x}", register, old, new)
+            },
+            Self::StackPointer { old, new } => write!(f, "sp: {} -> {}", old, new),
+        }
+    }
+}
+
+impl fmt::Display for RocCPUWatchpointHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "watchpoint {} at {:#06x} ({}): {}", self.id, self.pc, self.instruction, self.event)
+    }
+}
@@ -0,0 +1,262 @@
+//! The compact binary trace format.
+//!
+//! Every multi-byte number is big-endian, as in `.rocbin`.
+//! An entry's step is its position in the file.
+//!
+//! ```text
+//! magic           4 bytes   "RocT"
+//! version         u16       ROCTRACE_VERSION
+//! entries, until the end of the file, each:
+//!     pc          u32
+//!     instruction 1 to 4 bytes, encoded with `ProgramEncodable`
+//!     flags       u8        before in bits 0 to 3, after in bits 4 to 7,
+//!                           each as zero, carry, negative, overflow
+//!     registers   u8        how many changed
+//!         index   u8        into `RocCPURegister::ALL`
+//!         old     u8
+//!         new     u8
+//!     writes      u8        how many bytes of memory were written
+//!         address u16
+//!         old     u8
+//!         new     u8
+//!     stack       u8        how many pushes and pops
+//!         kind    u8        0 = push, 1 = pop
+//!         value   u8
+//! ```
+
+use std::io::{self, Write};
+
+use roc_cpu_traits::{ProgramDecodable, ProgramEncodable};
+use roc_cpu_types::*;
+use crate::runner::RocCPUFlags;
+
+use super::*;
+
+pub const ROCTRACE_MAGIC: [u8; 4] = *b"RocT";
+pub const ROCTRACE_VERSION: u16 = 1;
+
+const STACK_PUSH: u8 = 0;
+const STACK_POP: u8 = 1;
+
+
+/// A tracer that writes the binary format as
+/// instructions run.
+pub struct RocCPUBinaryTracer<W: Write> {
+    writer: W,
+
+    /// The first error writing ran into, after
+    /// which nothing more is written.
+    error: Option<io::Error>,
+}
+
+impl<W: Write> RocCPUBinaryTracer<W> {
+    /// Writes the header straight away.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(&ROCTRACE_MAGIC)?;
+        writer.write_all(&ROCTRACE_VERSION.to_be_bytes())?;
+        Ok(Self { writer, error: None })
+    }
+}
+
+impl<W: Write> RocCPUTracer for RocCPUBinaryTracer<W> {
+    fn record(&mut self, entry: &RocCPUTraceEntry) {
+        if self.error.is_none() {
+            self.error = self.writer.write_all(&entry_to_bytes(entry)).err();
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+
+impl RocCPUTrace {
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut output = vec![];
+        output.extend_from_slice(&ROCTRACE_MAGIC);
+        output.extend_from_slice(&ROCTRACE_VERSION.to_be_bytes());
+        for entry in &self.entries {
+            output.extend(entry_to_bytes(entry));
+        }
+        output
+    }
+
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, RocCPUTraceError> {
+        let mut reader = ByteReader { bytes, position: 0 };
+
+        if reader.take(4)? != ROCTRACE_MAGIC {
+            return Err(RocCPUTraceError::BadMagic);
+        }
+
+        let version = reader.u16()?;
+        if version != ROCTRACE_VERSION {
+            return Err(RocCPUTraceError::UnsupportedVersion(version));
+        }
+
+        let mut entries = vec![];
+        while !reader.at_end() {
+            let step = entries.len();
+            let bad_entry = |reason: String| RocCPUTraceError::BadEntry { entry: step, reason };
+
+            let pc = reader.u32()? as usize;
+            let (instruction, length) = RocCPUInstruction::decode(&bytes[reader.position..])
+                .map_err(|err| bad_entry(err.to_string()))?;
+            reader.take(length)?;
+
+            let mut entry = RocCPUTraceEntry::new(step as u64, pc, instruction);
+
+            let flags = reader.u8()?;
+            let (old, new) = (RocCPUFlags::from_bits(flags & 0xf), RocCPUFlags::from_bits(flags >> 4));
+            if old != new {
+                entry.flags = Some(RocCPUFlagsChange { old, new });
+            }
+
+            for _ in 0..reader.u8()? {
+                let index = reader.u8()?;
+                let register = *RocCPURegister::ALL.get(index as usize)
+                    .ok_or_else(|| bad_entry(format!("unknown register {}", index)))?;
+                entry.registers.push(RocCPURegisterChange { register, old: reader.u8()?, new: reader.u8()? });
+            }
+
+            for _ in 0..reader.u8()? {
+                entry.memory.push(RocCPUMemoryWrite { address: reader.u16()?, old: reader.u8()?, new: reader.u8()? });
+            }
+
+            for _ in 0..reader.u8()? {
+                let operation = match reader.u8()? {
+                    STACK_PUSH => RocCPUStackOperation::Push(reader.u8()?),
+                    STACK_POP => RocCPUStackOperation::Pop(reader.u8()?),
+                    kind => return Err(bad_entry(format!("unknown stack operation {}", kind))),
+                };
+                entry.stack.push(operation);
+            }
+
+            entries.push(entry);
+        }
+
+        Ok(Self { entries })
+    }
+}
+
+
+fn entry_to_bytes(entry: &RocCPUTraceEntry) -> Vec<u8> {
+    let mut output = vec![];
+    output.extend_from_slice(&(entry.pc as u32).to_be_bytes());
+    output.extend(entry.instruction.encode());
+
+    let flags = match entry.flags {
+        Some(change) => change.old.to_bits() | change.new.to_bits() << 4,
+        None => 0,
+    };
+    output.push(flags);
+
+    // An instruction changes at most a few of each,
+    // so the counts always fit in a byte.
+    output.push(entry.registers.len() as u8);
+    for change in &entry.registers {
+        let index = RocCPURegister::ALL.iter().position(|register| *register == change.register).unwrap();
+        output.extend_from_slice(&[index as u8, change.old, change.new]);
+    }
+
+    output.push(entry.memory.len() as u8);
+    for write in &entry.memory {
+        output.extend_from_slice(&write.address.to_be_bytes());
+        output.extend_from_slice(&[write.old, write.new]);
+    }
+
+    output.push(entry.stack.len() as u8);
+    for operation in &entry.stack {
+        match operation {
+            RocCPUStackOperation::Push(value) => output.extend_from_slice(&[STACK_PUSH, *value]),
+            RocCPUStackOperation::Pop(value) => output.extend_from_slice(&[STACK_POP, *value]),
+        }
+    }
+
+    output
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn at_end(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], RocCPUTraceError> {
+        let end = self.position.checked_add(count).ok_or(RocCPUTraceError::Truncated)?;
+        let taken = self.bytes.get(self.position..end).ok_or(RocCPUTraceError::Truncated)?;
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, RocCPUTraceError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, RocCPUTraceError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, RocCPUTraceError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::RocCPURunner;
+
+    /// The trace of a program that changes registers,
+    /// flags, memory and the stack.
+    fn recorded_trace() -> RocCPUTrace {
+        let program = crate::asm::assemble(
+            "PUT $ax, 200; ADDI $ax, 100; PUSH $ax; POP $bx; STORE 0x4000, $bx; EXIT;"
+        ).unwrap();
+        let recorder = RocCPUTraceRecorder::new();
+        let mut runner = RocCPURunner::default();
+        runner.load_program(&program);
+        runner.set_tracer(Box::new(recorder.clone()));
+        while runner.step() == crate::runner::RocCPUStepOutcome::Continued {}
+        recorder.trace()
+    }
+
+    #[test]
+    fn traces_round_trip() {
+        let trace = recorded_trace();
+        assert!(trace.entries.iter().any(|entry| entry.flags.is_some()));
+        assert!(trace.entries.iter().any(|entry| !entry.memory.is_empty()));
+        assert!(trace.entries.iter().any(|entry| !entry.stack.is_empty()));
+
+        assert_eq!(RocCPUTrace::from_binary(&trace.to_binary()).unwrap(), trace);
+        assert_eq!(RocCPUTrace::from_bytes(&trace.to_binary()).unwrap(), trace);
+    }
+
+    #[test]
+    fn tracers_write_what_to_binary_does() {
+        let trace = recorded_trace();
+        let mut tracer = RocCPUBinaryTracer::new(vec![]).unwrap();
+        for entry in &trace.entries {
+            tracer.record(entry);
+        }
+        tracer.finish().unwrap();
+        assert_eq!(tracer.writer, trace.to_binary());
+    }
+
+    #[test]
+    fn truncated_traces_are_errors() {
+        let bytes = recorded_trace().to_binary();
+        let err = RocCPUTrace::from_binary(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert!(matches!(err, RocCPUTraceError::Truncated), "{}", err);
+    }
+}
@@ -0,0 +1,262 @@
+//! Traces as JSON Lines: one object per instruction,
+//! such as
+//!
+//! ```text
+//! {"encoded":"4101","flags":null,"instruction":"PUSH $ax",
+//!  "memory":[],"pc":7,"registers":[],"stack":[{"push":3}],"step":2}
+//! ```
+//!
+//! (on a single line, with keys in alphabetical order). `encoded` is the instruction encoded
+//! with `ProgramEncodable`, in hex, and is what's read back;
+//! `instruction` is only there to be read by people. `flags`
+//! is `null` when the flags didn't change, or an object with
+//! `old` and `new` written like `Z-N-`. Register changes are
+//! objects with `register`, `old` and `new`, memory writes
+//! have `address`, `old` and `new`, and stack operations are
+//! `{"push":value}` or `{"pop":value}`.
+
+use std::io::{self, Write};
+
+use serde_json::{json, Value};
+use roc_cpu_traits::{ProgramDecodable, ProgramEncodable};
+use roc_cpu_types::*;
+use crate::runner::RocCPUFlags;
+
+use super::*;
+
+
+/// A tracer that writes JSON Lines as instructions run.
+pub struct RocCPUJsonLinesTracer<W: Write> {
+    writer: W,
+
+    /// The first error writing ran into, after
+    /// which nothing more is written.
+    error: Option<io::Error>,
+}
+
+impl<W: Write> RocCPUJsonLinesTracer<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, error: None }
+    }
+}
+
+impl<W: Write> RocCPUTracer for RocCPUJsonLinesTracer<W> {
+    fn record(&mut self, entry: &RocCPUTraceEntry) {
+        if self.error.is_none() {
+            self.error = serde_json::to_writer(&mut self.writer, &entry.to_json_value())
+                .map_err(io::Error::from)
+                .and_then(|()| writeln!(self.writer))
+                .err();
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+
+impl RocCPUTraceEntry {
+    /// The entry as one line of JSON, without the newline.
+    pub fn to_json(&self) -> String {
+        self.to_json_value().to_string()
+    }
+
+    /// The entry as the JSON value `to_json` writes and
+    /// `from_json` reads.
+    fn to_json_value(&self) -> Value {
+        let registers: Vec<_> = self.registers.iter()
+            .map(|change| json!({
+                "register": change.register.to_string(),
+                "old": change.old,
+                "new": change.new,
+            }))
+            .collect();
+        let flags = self.flags.map(|change| json!({
+            "old": flags_text(change.old),
+            "new": flags_text(change.new),
+        }));
+        let memory: Vec<_> = self.memory.iter()
+            .map(|write| json!({ "address": write.address, "old": write.old, "new": write.new }))
+            .collect();
+        let stack: Vec<_> = self.stack.iter()
+            .map(|operation| match operation {
+                RocCPUStackOperation::Push(value) => json!({ "push": value }),
+                RocCPUStackOperation::Pop(value) => json!({ "pop": value }),
+            })
+            .collect();
+        let encoded: String = self.instruction.encode().iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        json!({
+            "step": self.step,
+            "pc": self.pc,
+            "instruction": self.instruction.to_string(),
+            "encoded": encoded,
+            "registers": registers,
+            "flags": flags,
+            "memory": memory,
+            "stack": stack,
+        })
+    }
+
+    /// Reads back a line written by `to_json`.
+    pub fn from_json(line: &str) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(line).map_err(|err| err.to_string())?;
+
+        let encoded = text(field(&value, "encoded")?)?;
+        let bytes = (0..encoded.len())
+            .step_by(2)
+            .map(|index| {
+                encoded.get(index..index + 2)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or("\"encoded\" isn't hex")
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let (instruction, _) = RocCPUInstruction::decode(&bytes).map_err(|err| err.to_string())?;
+
+        let mut entry = Self::new(
+            number(field(&value, "step")?)?,
+            number(field(&value, "pc")?)? as usize,
+            instruction,
+        );
+
+        for change in array(field(&value, "registers")?)? {
+            let name = text(field(change, "register")?)?;
+            let register = *RocCPURegister::ALL.iter()
+                .find(|register| register.to_string() == name)
+                .ok_or_else(|| format!("unknown register \"{}\"", name))?;
+            entry.registers.push(RocCPURegisterChange {
+                register,
+                old: byte(field(change, "old")?)?,
+                new: byte(field(change, "new")?)?,
+            });
+        }
+
+        let flags = field(&value, "flags")?;
+        if !flags.is_null() {
+            entry.flags = Some(RocCPUFlagsChange {
+                old: parse_flags(text(field(flags, "old")?)?)?,
+                new: parse_flags(text(field(flags, "new")?)?)?,
+            });
+        }
+
+        for write in array(field(&value, "memory")?)? {
+            entry.memory.push(RocCPUMemoryWrite {
+                address: u16::try_from(number(field(write, "address")?)?)
+                    .map_err(|_| "\"address\" is past the end of memory")?,
+                old: byte(field(write, "old")?)?,
+                new: byte(field(write, "new")?)?,
+            });
+        }
+
+        for operation in array(field(&value, "stack")?)? {
+            let operation = match (field(operation, "push"), field(operation, "pop")) {
+                (Ok(value), _) => RocCPUStackOperation::Push(byte(value)?),
+                (_, Ok(value)) => RocCPUStackOperation::Pop(byte(value)?),
+                _ => return Err("a stack operation is neither \"push\" nor \"pop\"".to_string()),
+            };
+            entry.stack.push(operation);
+        }
+
+        Ok(entry)
+    }
+}
+
+impl RocCPUTrace {
+    pub fn to_json_lines(&self) -> String {
+        self.entries.iter()
+            .map(|entry| entry.to_json() + "\n")
+            .collect()
+    }
+
+    /// Reads one entry from every line that isn't blank.
+    pub fn from_json_lines(text: &str) -> Result<Self, RocCPUTraceError> {
+        let entries = text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(entry, line)| {
+                RocCPUTraceEntry::from_json(line)
+                    .map_err(|reason| RocCPUTraceError::BadEntry { entry, reason })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { entries })
+    }
+}
+
+
+/// The reverse of `flags_text`.
+fn parse_flags(text: &str) -> Result<RocCPUFlags, String> {
+    let chars: Vec<_> = text.chars().collect();
+    if chars.len() != 4 {
+        return Err(format!("\"{}\" isn't a set of flags", text));
+    }
+    Ok(RocCPUFlags {
+        zero: chars[0] == 'Z',
+        carry: chars[1] == 'C',
+        negative: chars[2] == 'N',
+        overflow: chars[3] == 'V',
+    })
+}
+
+
+/// `value`'s field `name`.
+fn field<'a>(value: &'a Value, name: &str) -> Result<&'a Value, String> {
+    match value {
+        Value::Object(fields) => fields.get(name).ok_or_else(|| format!("missing \"{}\"", name)),
+        _ => Err(format!("expected an object with \"{}\"", name)),
+    }
+}
+
+fn number(value: &Value) -> Result<u64, String> {
+    value.as_u64().ok_or_else(|| "expected a number".to_string())
+}
+
+fn byte(value: &Value) -> Result<u8, String> {
+    u8::try_from(number(value)?).map_err(|_| "expected a byte".to_string())
+}
+
+fn text(value: &Value) -> Result<&str, String> {
+    value.as_str().ok_or_else(|| "expected a string".to_string())
+}
+
+fn array(value: &Value) -> Result<&[Value], String> {
+    value.as_array().map(Vec::as_slice).ok_or_else(|| "expected an array".to_string())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deeply_nested_lines_are_bad_entries() {
+        let line = "[".repeat(200_000);
+        let err = RocCPUTrace::from_json_lines(&line).unwrap_err();
+        assert!(matches!(err, RocCPUTraceError::BadEntry { entry: 0, .. }), "{}", err);
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let mut entry = RocCPUTraceEntry::new(2, 7, RocCPUInstruction::Push(RocCPURegister::GeneralPurposeA));
+        entry.registers.push(RocCPURegisterChange { register: RocCPURegister::GeneralPurposeB, old: 1, new: 2 });
+        entry.memory.push(RocCPUMemoryWrite { address: 0x4000, old: 3, new: 4 });
+        entry.flags = Some(RocCPUFlagsChange {
+            old: RocCPUFlags::default(),
+            new: RocCPUFlags { zero: true, negative: true, ..Default::default() },
+        });
+        entry.stack.push(RocCPUStackOperation::Push(3));
+
+        let trace = RocCPUTrace { entries: vec![entry] };
+        assert_eq!(RocCPUTrace::from_json_lines(&trace.to_json_lines()).unwrap(), trace);
+
+        let mut tracer = RocCPUJsonLinesTracer::new(vec![]);
+        tracer.record(&trace.entries[0]);
+        tracer.finish().unwrap();
+        assert_eq!(String::from_utf8(tracer.writer).unwrap(), trace.to_json_lines());
+    }
+}
@@ -0,0 +1,281 @@
+//! Recording what every executed instruction did, to
+//! compare two runs of a program afterwards.
+//!
+//! Hand a `RocCPUTracer` to `RocCPURunner::set_tracer`.
+//! Traces can be kept in memory with `RocCPUTraceRecorder`,
+//! or written out as they happen, one JSON object per line
+//! with `RocCPUJsonLinesTracer`, or compactly with
+//! `RocCPUBinaryTracer`. `RocCPUTrace::read_file` reads
+//! either back.
+
+mod binary;
+mod json;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use roc_cpu_types::*;
+use crate::runner::RocCPUFlags;
+
+pub use binary::*;
+pub use json::*;
+
+
+/// A register that an instruction changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RocCPURegisterChange {
+    pub register: RocCPURegister,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// The flags, when an instruction changed them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RocCPUFlagsChange {
+    pub old: RocCPUFlags,
+    pub new: RocCPUFlags,
+}
+
+/// A byte an instruction wrote to memory. Writes that
+/// leave the byte as it was are still recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RocCPUMemoryWrite {
+    pub address: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// A value an instruction pushed onto, or popped
+/// off, the stack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RocCPUStackOperation {
+    Push(u8),
+    Pop(u8),
+}
+
+/// Everything one instruction did.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RocCPUTraceEntry {
+    /// How many instructions were traced before this one.
+    pub step: u64,
+
+    /// Where the instruction was, in the same terms as
+    /// `RocCPURunner::program_counter`.
+    pub pc: usize,
+    pub instruction: RocCPUInstruction,
+
+    /// Only registers whose value changed.
+    pub registers: Vec<RocCPURegisterChange>,
+    pub flags: Option<RocCPUFlagsChange>,
+    pub memory: Vec<RocCPUMemoryWrite>,
+
+    /// In the order they happened.
+    pub stack: Vec<RocCPUStackOperation>,
+}
+
+impl RocCPUTraceEntry {
+    pub(crate) fn new(step: u64, pc: usize, instruction: RocCPUInstruction) -> Self {
+        Self {
+            step,
+            pc,
+            instruction,
+            registers: vec![],
+            flags: None,
+            memory: vec![],
+            stack: vec![],
+        }
+    }
+
+    /// Whether both entries did the same thing, whatever
+    /// their step numbers.
+    pub fn same_as(&self, other: &RocCPUTraceEntry) -> bool {
+        self.pc == other.pc
+            && self.instruction == other.instruction
+            && self.registers == other.registers
+            && self.flags == other.flags
+            && self.memory == other.memory
+            && self.stack == other.stack
+    }
+}
+
+/// One line, such as
+/// `#12 0x0004 ADD $ax, $bx  $ax 0x03->0x06`.
+impl fmt::Display for RocCPUTraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {:#06x} {}", self.step, self.pc, self.instruction)?;
+        for change in &self.registers {
+            write!(f, "  {} {:#04x}->{:#04x}", change.register, change.old, change.new)?;
+        }
+        if let Some(change) = self.flags {
+            write!(f, "  flags {}->{}", flags_text(change.old), flags_text(change.new))?;
+        }
+        for write in &self.memory {
+            write!(f, "  [{:#06x}] {:#04x}->{:#04x}", write.address, write.old, write.new)?;
+        }
+        for operation in &self.stack {
+            match operation {
+                RocCPUStackOperation::Push(value) => write!(f, "  push {:#04x}", value)?,
+                RocCPUStackOperation::Pop(value) => write!(f, "  pop {:#04x}", value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The flags as `ZCNV`, with `-` for each one
+/// that isn't set.
+pub(crate) fn flags_text(flags: RocCPUFlags) -> String {
+    let flag = |set: bool, name: char| if set { name } else { '-' };
+    [
+        flag(flags.zero, 'Z'),
+        flag(flags.carry, 'C'),
+        flag(flags.negative, 'N'),
+        flag(flags.overflow, 'V'),
+    ].iter().collect()
+}
+
+
+/// Somewhere for a `RocCPURunner` to send what each
+/// instruction did.
+pub trait RocCPUTracer {
+    /// Called after every instruction that runs to
+    /// completion. Instructions that fault aren't recorded.
+    fn record(&mut self, entry: &RocCPUTraceEntry);
+
+    /// Called when the tracer is taken off the runner.
+    /// Tracers that write as they go flush here, and
+    /// report the first error they ran into.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A tracer that keeps every entry in memory.
+///
+/// Clones share the same entries, so keep a clone
+/// around after handing one to a `RocCPURunner`.
+#[derive(Clone, Debug, Default)]
+pub struct RocCPUTraceRecorder {
+    entries: Rc<RefCell<Vec<RocCPUTraceEntry>>>,
+}
+
+impl RocCPUTraceRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A copy of everything recorded so far.
+    pub fn trace(&self) -> RocCPUTrace {
+        RocCPUTrace { entries: self.entries.borrow().clone() }
+    }
+}
+
+impl RocCPUTracer for RocCPUTraceRecorder {
+    fn record(&mut self, entry: &RocCPUTraceEntry) {
+        self.entries.borrow_mut().push(entry.clone());
+    }
+}
+
+
+#[derive(Debug)]
+pub enum RocCPUTraceError {
+    Io(io::Error),
+
+    /// A binary trace doesn't start with `ROCTRACE_MAGIC`.
+    BadMagic,
+
+    /// The trace was written by a newer version
+    /// of the binary format.
+    UnsupportedVersion(u16),
+
+    /// The trace ends part way through an entry.
+    Truncated,
+
+    /// An entry couldn't be read. `entry` counts from 0.
+    BadEntry { entry: usize, reason: String },
+}
+
+impl fmt::Display for RocCPUTraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::BadMagic => write!(f, "not a binary trace"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported trace version {}", version)
+            },
+            Self::Truncated => write!(f, "trace ends unexpectedly"),
+            Self::BadEntry { entry, reason } => write!(f, "entry {}: {}", entry, reason),
+        }
+    }
+}
+
+impl std::error::Error for RocCPUTraceError {}
+
+impl From<io::Error> for RocCPUTraceError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+
+/// Where two traces first stop agreeing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RocCPUTraceDivergence {
+    /// Both traces have an entry at `index`, and
+    /// they're different.
+    Differs { index: usize },
+
+    /// The left trace ends at `index`, but the right
+    /// one carries on.
+    LeftEnded { index: usize },
+
+    /// The right trace ends at `index`, but the left
+    /// one carries on.
+    RightEnded { index: usize },
+}
+
+/// A whole recorded trace.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RocCPUTrace {
+    pub entries: Vec<RocCPUTraceEntry>,
+}
+
+impl RocCPUTrace {
+    /// Reads a binary trace, or failing that, JSON Lines.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RocCPUTraceError> {
+        if bytes.starts_with(&ROCTRACE_MAGIC) {
+            return Self::from_binary(bytes);
+        }
+
+        let text = std::str::from_utf8(bytes).map_err(|_| RocCPUTraceError::BadMagic)?;
+        Self::from_json_lines(text)
+    }
+
+    pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Self, RocCPUTraceError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// The first entry where the traces disagree, or
+    /// `None` if they're the same. Step numbers are
+    /// ignored, so traces started at different points
+    /// can be compared.
+    pub fn first_divergence(&self, other: &RocCPUTrace) -> Option<RocCPUTraceDivergence> {
+        for (index, (left, right)) in self.entries.iter().zip(&other.entries).enumerate() {
+            if !left.same_as(right) {
+                return Some(RocCPUTraceDivergence::Differs { index });
+            }
+        }
+
+        let common = self.entries.len().min(other.entries.len());
+        if self.entries.len() < other.entries.len() {
+            Some(RocCPUTraceDivergence::LeftEnded { index: common })
+        } else if other.entries.len() < self.entries.len() {
+            Some(RocCPUTraceDivergence::RightEnded { index: common })
+        } else {
+            None
+        }
+    }
+}
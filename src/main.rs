@@ -30,7 +30,8 @@ fn main() {
         PUTMEM 0x87, 0x51, 0xFF;
         PUTMEM 0x87, 0x52, 0x00;
 
-        JUMP @lbl; // <= JUMPS TO THE RETURN STATEMENT
+        CMP $ax, $ax;
+        JZ @lbl; // <= JUMPS TO THE RETURN STATEMENT
 
         RENDER; // <= SKIPS THIS LINE
         WAIT 3; // <= SKIPS THIS LINE
@@ -42,55 +43,23 @@ fn main() {
 
 
     let program = roc_asm! {
-        PUT $f1, 5;
-        PUT $f2, 7;
-        CALL @multiply;
-        MOV $ret, $fret;
+        PUT $ax, 5;
+        PUT $bx, 7;
+        MUL $ax, $bx;
+        MOV $ret, $ax;
         EXIT;
-
-        // FUNCTION MULTIPLY
-        // 
-        // Calculates $f1 x $f2
-        // Internally uses $f1, $f2, $ax-$dx
-        // Returns all registers to their original states
-        // Returns value goes to $fret
-        @multiply
-            PUSH $f1;
-            PUSH $f2;
-            PUSH $ax;
-            PUSH $bx;
-            PUSH $cx;
-
-            // Put in default values
-            PUT $ax, 1;
-            MOV $bx, $f1;
-            MOV $cx, $f2;
-            PUT $dx, 0;
-            PUT $fret, 0;
-
-        @multiplyloop
-            // Check if bx (ctr) == 0
-            CMP $bx, $dx;
-            JZ @multiplyfinish;
-
-            ADD $fret, $cx;
-            SUB $bx, $ax;
-            JUMP @multiplyloop;
-
-        @multiplyfinish
-            POP $cx;
-            POP $bx;
-            POP $ax;
-            POP $f2;
-            POP $f1;
-            RETURN;
     };
 
 
-    let mut runner = RocCPURunner::new(Some(&program));
-    let retval = runner.execute();
-
-    println!("Execution completed with exit code {}", retval);
+    let mut runner = RocCPURunner::new(Some(&program), Box::new(RocCPUSdlDisplay::new()));
+    match runner.execute() {
+        Ok(state) => {
+            println!("Execution completed with exit code {}", state.return_value());
+        },
+        Err(fault) => {
+            println!("Execution faulted: {}", fault);
+        }
+    }
 }
 
 //WAIT 5;
\ No newline at end of file